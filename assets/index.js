@@ -1,15 +1,46 @@
 Promise.sleep = ms => new Promise(r => setTimeout(r, ms));
 
 (async () => {
+    let search = document.getElementById("services-search");
+    let list = document.getElementById("services-list");
     let sse = new EventSource("/sse");
     sse.onopen = e => {
         console.trace("sse:open");
         delete sse.onerror;
     };
+    // leave the connection open so the browser reconnects with
+    // `Last-Event-ID` and the server sends a fresh list
     sse.onerror = e => {
         console.trace("sse:error");
-        sse.close();
     }
+    for (let kind of ["created", "modified", "deleted"]) {
+        sse.addEventListener(kind, ev => {
+            console.log(`sse ${kind} event`, JSON.parse(ev.data));
+        });
+    }
+    sse.addEventListener("reloaded", ev => {
+        let reloaded = document.getElementById("last-reloaded");
+        if (reloaded) {
+            reloaded.dateTime = ev.data;
+            reloaded.textContent = ev.data;
+        }
+    });
+    // the event has every service, refetch with this page's query so a
+    // `?page=`, `?per_page=` or `?tag=` view stays as it is
+    sse.addEventListener("services", async () => {
+        console.log("sse services event");
+        if (search?.value) {
+            search.dispatchEvent(new Event("input"));
+            return;
+        }
+        let res = await fetch(`/fragment/services${location.search}`);
+        if (!res.ok) {
+            console.error("refreshing services failed", res.status);
+            return;
+        }
+        list.innerHTML = await res.text();
+        applyCollapsed();
+    });
     let to;
     sse.onmessage = ev => {
         delete sse.onerror;
@@ -21,5 +52,52 @@ Promise.sleep = ms => new Promise(r => setTimeout(r, ms));
             location = location;
         }, 200);
     }
-    
+
+    // search results are rendered with every category open. The cookie is
+    // already url encoded so it's parsed as is
+    let collapsedCookie = () => {
+        let cookie = document.cookie
+            .split("; ")
+            .find(c => c.startsWith("collapsed="));
+        return new URLSearchParams(cookie?.slice("collapsed=".length) ?? "");
+    };
+    let isCollapsed = (details, cookie) => details.dataset.pinned !== undefined
+        ? cookie.has("p")
+        : cookie.getAll("c").includes(details.dataset.category);
+    let applyCollapsed = () => {
+        let cookie = collapsedCookie();
+        for (let details of list.querySelectorAll("details[data-category], details[data-pinned]")) {
+            details.open = !isCollapsed(details, cookie);
+        }
+    };
+    // `toggle` doesn't bubble so listen while capturing
+    list.addEventListener("toggle", ev => {
+        let details = ev.target;
+        let dataset = details.dataset ?? {};
+        if (dataset.category === undefined && dataset.pinned === undefined) {
+            return;
+        }
+        if (isCollapsed(details, collapsedCookie()) !== details.open) {
+            return;
+        }
+        let section = dataset.pinned !== undefined
+            ? "pinned=true"
+            : `name=${encodeURIComponent(dataset.category)}`;
+        fetch(`/toggle-category?${section}&open=${details.open}`, { redirect: "manual" })
+            .catch(e => console.error("failed to save category state", e));
+    }, true);
+
+    let searchTo;
+    search?.addEventListener("input", () => {
+        clearTimeout(searchTo);
+        searchTo = setTimeout(async () => {
+            let res = await fetch(`/search?q=${encodeURIComponent(search.value)}`);
+            if (!res.ok) {
+                console.error("search failed", res.status);
+                return;
+            }
+            list.innerHTML = await res.text();
+            applyCollapsed();
+        }, 150);
+    });
 })().catch(e => console.error("ERROR from main", e))
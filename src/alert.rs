@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{health::Status, Service};
+
+pub const ENV_VAR_ALERT_WEBHOOK: &str = "HOME_SERVICE_ALERT_WEBHOOK";
+pub const ENV_VAR_ALERT_COOLDOWN: &str = "HOME_SERVICE_ALERT_COOLDOWN_SECS";
+pub const ENV_VAR_ALERT_FORMAT: &str = "HOME_SERVICE_ALERT_FORMAT";
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(600);
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts to a webhook when a service goes from up to down
+#[derive(Debug)]
+pub struct Alerter {
+    webhook: reqwest::Url,
+    /// Minimum time between alerts for the same service so a flapping
+    /// service doesn't flood the webhook
+    cooldown: Duration,
+    format: AlertFormat,
+    last_sent: HashMap<String, Instant>,
+}
+
+/// The shape of the webhook body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlertFormat {
+    /// The [`Payload`] fields as they are
+    #[default]
+    Json,
+    /// `{"text": ...}` for a Slack incoming webhook
+    Slack,
+    /// `{"content": ...}` for a Discord webhook
+    Discord,
+}
+
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    service: &'a str,
+    url: &'a str,
+    status: &'static str,
+    timestamp: String,
+}
+
+impl Payload<'_> {
+    fn body(&self, format: AlertFormat) -> serde_json::Value {
+        let message = || {
+            format!(
+                "{} is {}: {} ({})",
+                self.service, self.status, self.url, self.timestamp
+            )
+        };
+        match format {
+            AlertFormat::Json => serde_json::json!(self),
+            AlertFormat::Slack => serde_json::json!({ "text": message() }),
+            AlertFormat::Discord => serde_json::json!({ "content": message() }),
+        }
+    }
+}
+
+impl Alerter {
+    /// `None` unless a valid webhook url has been configured
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(ENV_VAR_ALERT_WEBHOOK).ok()?;
+        let webhook = match reqwest::Url::parse(value.trim()) {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::warn!(
+                    "invalid {ENV_VAR_ALERT_WEBHOOK} `{value}`, alerts are disabled: {e}"
+                );
+                return None;
+            }
+        };
+        tracing::info!("sending alerts to `{webhook}`");
+        Some(Self {
+            webhook,
+            cooldown: cooldown_from_env(),
+            format: format_from_env(),
+            last_sent: HashMap::new(),
+        })
+    }
+
+    /// Alert for every service that was up before this round of checks and
+    /// is down now
+    pub async fn notify(
+        &mut self,
+        client: &reqwest::Client,
+        previous: &HashMap<String, Status>,
+        current: &HashMap<String, Status>,
+        services: &[Service],
+    ) {
+        for service in services {
+            let went_down = previous.get(&service.name).is_some_and(|s| s.is_up())
+                && current.get(&service.name) == Some(&Status::Down);
+            if !went_down {
+                continue;
+            }
+            let now = Instant::now();
+            if let Some(last) = self.last_sent.get(&service.name) {
+                if now.duration_since(*last) < self.cooldown {
+                    tracing::debug!("not alerting for `{}`, still cooling down", service.name);
+                    continue;
+                }
+            }
+            self.last_sent.insert(service.name.clone(), now);
+            self.send(client, service, Status::Down).await;
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, service: &Service, status: Status) {
+        let payload = Payload {
+            service: &service.name,
+            url: &service.url,
+            status: Status::label(Some(status)),
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        };
+        match client
+            .post(self.webhook.clone())
+            .timeout(SEND_TIMEOUT)
+            .json(&payload.body(self.format))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+        {
+            Ok(_) => tracing::info!("sent alert for `{}`", service.name),
+            Err(e) => tracing::warn!("failed to send alert for `{}`: {e}", service.name),
+        }
+    }
+}
+
+fn cooldown_from_env() -> Duration {
+    let Ok(value) = std::env::var(ENV_VAR_ALERT_COOLDOWN) else {
+        return DEFAULT_COOLDOWN;
+    };
+    value
+        .trim()
+        .parse()
+        .map(Duration::from_secs)
+        .unwrap_or_else(|e| {
+            tracing::warn!("invalid {ENV_VAR_ALERT_COOLDOWN} `{value}`: {e}");
+            DEFAULT_COOLDOWN
+        })
+}
+
+fn format_from_env() -> AlertFormat {
+    parse_format(std::env::var(ENV_VAR_ALERT_FORMAT).ok().as_deref())
+}
+
+fn parse_format(value: Option<&str>) -> AlertFormat {
+    let Some(value) = value else {
+        return AlertFormat::default();
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "json" => AlertFormat::Json,
+        "slack" => AlertFormat::Slack,
+        "discord" => AlertFormat::Discord,
+        _ => {
+            tracing::warn!(
+                "invalid {ENV_VAR_ALERT_FORMAT} `{value}`, expected json, slack or discord"
+            );
+            AlertFormat::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::State, Json, Router};
+
+    use super::*;
+    use crate::test_support;
+
+    type Received = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    /// A webhook recording every body posted to it
+    async fn webhook() -> (reqwest::Url, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/hook",
+                axum::routing::post(
+                    |State(received): State<Received>, Json(body): Json<serde_json::Value>| async move {
+                        received.lock().unwrap().push(body);
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let addr = test_support::serve(app).await;
+        (format!("http://{addr}/hook").parse().unwrap(), received)
+    }
+
+    fn alerter(webhook: reqwest::Url, format: AlertFormat) -> Alerter {
+        Alerter {
+            webhook,
+            cooldown: DEFAULT_COOLDOWN,
+            format,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    fn statuses(status: Status) -> HashMap<String, Status> {
+        HashMap::from([("plex".to_string(), status)])
+    }
+
+    #[tokio::test]
+    async fn alerts_once_when_a_service_goes_down() {
+        let (url, received) = webhook().await;
+        let mut alerter = alerter(url, AlertFormat::Json);
+        let client = reqwest::Client::new();
+        let services: Vec<Service> =
+            vec![toml::from_str("name = \"plex\"\nurl = \"http://plex.local\"").unwrap()];
+        let (up, down) = (statuses(Status::Up), statuses(Status::Down));
+        alerter.notify(&client, &up, &up, &services).await;
+        alerter.notify(&client, &up, &down, &services).await;
+        // still down, then flapping within the cooldown
+        alerter.notify(&client, &down, &down, &services).await;
+        alerter.notify(&client, &up, &down, &services).await;
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1, "{received:?}");
+        assert_eq!(received[0]["service"], "plex");
+        assert_eq!(received[0]["url"], "http://plex.local");
+        assert_eq!(received[0]["status"], "down");
+        assert!(received[0]["timestamp"].is_string());
+    }
+
+    #[tokio::test]
+    async fn chat_formats_wrap_a_message() {
+        let services: Vec<Service> =
+            vec![toml::from_str("name = \"plex\"\nurl = \"http://plex.local\"").unwrap()];
+        let (up, down) = (statuses(Status::Up), statuses(Status::Down));
+        for (format, key) in [
+            (AlertFormat::Slack, "text"),
+            (AlertFormat::Discord, "content"),
+        ] {
+            let (url, received) = webhook().await;
+            alerter(url, format)
+                .notify(&reqwest::Client::new(), &up, &down, &services)
+                .await;
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1, "{received:?}");
+            let body = received[0].as_object().unwrap();
+            assert_eq!(body.len(), 1, "{body:?}");
+            let message = body[key].as_str().unwrap();
+            assert!(
+                message.starts_with("plex is down: http://plex.local ("),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn formats_are_parsed_ignoring_case() {
+        assert_eq!(parse_format(None), AlertFormat::Json);
+        assert_eq!(parse_format(Some(" Slack ")), AlertFormat::Slack);
+        assert_eq!(parse_format(Some("DISCORD")), AlertFormat::Discord);
+        assert_eq!(parse_format(Some("teams")), AlertFormat::Json);
+    }
+}
@@ -0,0 +1,377 @@
+use std::io::ErrorKind;
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    expand_env, invalidate_cfg_cache, read_cfg, read_cfg_text, validate_url, CfgFormat, Service,
+    ServiceDocument, Services, CFG_PATH,
+};
+
+type ApiError = (StatusCode, Json<ErrorBody>);
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    error: String,
+}
+
+fn api_err(status: StatusCode, e: impl ToString) -> ApiError {
+    let error = e.to_string();
+    tracing::warn!("api error {status}: {error}");
+    (status, Json(ErrorBody { error }))
+}
+
+pub async fn list_services() -> Result<Json<Services>, ApiError> {
+    let cfg = read_cfg()
+        .await
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(cfg.without_disabled()))
+}
+
+/// Persist a new service as `<slug>.<ext>` in the root of the cfg dir, the
+/// body can be json, toml or yaml and the file is written in the same format
+pub async fn create_service(
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<Service>), ApiError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let format = CfgFormat::from_content_type(content_type).ok_or_else(|| {
+        api_err(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("expected a json, toml or yaml body, found `{content_type}`"),
+        )
+    })?;
+    let service: Service = format.parse(&body).map_err(|e| {
+        api_err(
+            StatusCode::BAD_REQUEST,
+            format!("invalid {} body: {e}", format.name()),
+        )
+    })?;
+    if service.name.trim().is_empty() {
+        return Err(api_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "name is required",
+        ));
+    }
+    // checked the way it will be read back, the file keeps any `${VAR}` as is
+    let mut expanded = service.clone();
+    expanded.expand_env();
+    validate_url(&expanded.url).map_err(|e| {
+        api_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("`{}` {e}", service.name),
+        )
+    })?;
+    let slug = slugify(&service.name);
+    if slug.is_empty() {
+        return Err(api_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("`{}` has no characters usable in a file name", service.name),
+        ));
+    }
+    let cfg = read_cfg()
+        .await
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if cfg.services.iter().any(|s| s.name == expanded.name) {
+        return Err(api_err(
+            StatusCode::CONFLICT,
+            format!("a service named `{}` already exists", service.name),
+        ));
+    }
+    let contents = format
+        .serialize(&service)
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    // the last dir has the highest priority so nothing can shadow the new service
+    let path = CFG_PATH
+        .get()
+        .and_then(|dirs| dirs.last())
+        .ok_or_else(|| api_err(StatusCode::INTERNAL_SERVER_ERROR, "CFG_PATH is unset!"))?
+        .join(format!("{slug}.{}", format.name()));
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await
+        .map_err(|e| {
+            if e.kind() == ErrorKind::AlreadyExists {
+                api_err(
+                    StatusCode::CONFLICT,
+                    format!("`{}` already exists", path.display()),
+                )
+            } else {
+                api_err(StatusCode::INTERNAL_SERVER_ERROR, e)
+            }
+        })?;
+    // tokio finishes writes in the background, flush so the file is complete
+    // before anything reads it back
+    async {
+        file.write_all(contents.as_bytes()).await?;
+        file.flush().await
+    }
+    .await
+    .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!("created `{}` for `{}`", path.display(), service.name);
+    invalidate_cfg_cache();
+    Ok((StatusCode::CREATED, Json(service)))
+}
+
+/// Remove the service named `name` from the file it was read from, deleting
+/// the file entirely when it was the only service in it
+pub async fn delete_service(Path(name): Path<String>) -> Result<StatusCode, ApiError> {
+    let cfg = read_cfg()
+        .await
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let source = cfg
+        .services
+        .iter()
+        .find(|s| s.name == name)
+        .and_then(|s| s.source.clone())
+        .ok_or_else(|| api_err(StatusCode::NOT_FOUND, format!("no service named `{name}`")))?;
+    let s = read_cfg_text(&source)
+        .await
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let (format, doc) = CfgFormat::parse_file(&source, &s)
+        .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let remaining: Vec<Service> = match doc {
+        ServiceDocument::One(_) => Vec::new(),
+        ServiceDocument::Many(services) => services
+            .services
+            .into_iter()
+            .filter(|s| expand_env(&s.name) != name)
+            .collect(),
+    };
+    if remaining.is_empty() {
+        tokio::fs::remove_file(&source)
+            .await
+            .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("removed `{}` for `{name}`", source.display());
+    } else {
+        let contents = format
+            .serialize(&Services {
+                services: remaining,
+                ..Default::default()
+            })
+            .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tokio::fs::write(&source, contents)
+            .await
+            .map_err(|e| api_err(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("removed `{name}` from `{}`", source.display());
+    }
+    invalidate_cfg_cache();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lowercase ascii letters and digits with any other runs of characters
+/// replaced by a single `-`, this can never contain a path separator or `..`
+fn slugify(name: &str) -> String {
+    let mut ret = String::with_capacity(name.len());
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() {
+            ret.push(ch.to_ascii_lowercase());
+        } else if !ret.is_empty() && !ret.ends_with('-') {
+            ret.push('-');
+        }
+    }
+    while ret.ends_with('-') {
+        ret.pop();
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+    };
+
+    use super::slugify;
+    use crate::{read_cfg, test_support};
+
+    async fn post(content_type: &str, body: &str) -> axum::response::Response {
+        let req = Request::post("/api/services")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        test_support::send(req).await
+    }
+
+    #[tokio::test]
+    async fn lists_enabled_services_as_json() {
+        let res = test_support::get("/api/services").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[axum::http::header::CONTENT_TYPE],
+            "application/json"
+        );
+        let body: serde_json::Value = serde_json::from_str(&test_support::text(res).await).unwrap();
+        let names: Vec<&str> = body["service"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"plex"));
+        assert!(!names.contains(&"hidden"));
+        assert_eq!(body["service"][0]["url"], "http://plex.local");
+    }
+
+    #[tokio::test]
+    async fn creates_a_service_file_and_rejects_duplicates() {
+        let _cfg = test_support::lock_cfg().await;
+        let body = r#"{"name": "zz created", "url": "http://created.local"}"#;
+        let res = post("application/json", body).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let path = test_support::cfg_dir().join("zz-created.json");
+        assert!(path.exists());
+        let cfg = read_cfg().await.unwrap();
+        assert!(cfg.services.iter().any(|s| s.name == "zz created"));
+
+        let res = post("application/json", body).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_services() {
+        let no_name = r#"{"name": " ", "url": "http://a.local"}"#;
+        let res = post("application/json", no_name).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let bad_url = r#"{"name": "zz bad", "url": "htp://a"}"#;
+        let res = post("application/json", bad_url).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn slugs_never_contain_separators() {
+        assert_eq!(slugify("../../etc/passwd"), "etc-passwd");
+        assert_eq!(slugify("My Service!"), "my-service");
+        assert_eq!(slugify("..."), "");
+    }
+
+    async fn delete(name: &str) -> StatusCode {
+        let req = Request::delete(format!("/api/services/{name}"))
+            .body(Body::empty())
+            .unwrap();
+        test_support::send(req).await.status()
+    }
+
+    #[tokio::test]
+    async fn deleting_the_only_service_removes_its_file() {
+        let _cfg = test_support::lock_cfg().await;
+        let path = test_support::cfg_dir().join("zz-single.toml");
+        std::fs::write(
+            &path,
+            "name = \"zz single\"\nurl = \"http://single.local\"\n",
+        )
+        .unwrap();
+        assert_eq!(delete("zz%20single").await, StatusCode::NO_CONTENT);
+        assert!(!path.exists());
+        assert_eq!(delete("zz%20single").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn deleting_from_a_shared_file_rewrites_it() {
+        let _cfg = test_support::lock_cfg().await;
+        let path = test_support::cfg_dir().join("zz-multi.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [[service]]
+            name = "zz first"
+            url = "http://first.local"
+
+            [[service]]
+            name = "zz second"
+            url = "http://second.local"
+            tags = ["kept"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(delete("zz%20first").await, StatusCode::NO_CONTENT);
+        let rewritten = std::fs::read_to_string(&path).unwrap();
+        assert!(!rewritten.contains("zz first"), "{rewritten}");
+        assert!(rewritten.contains("name = \"zz second\""), "{rewritten}");
+        assert!(rewritten.contains("tags = [\"kept\"]"), "{rewritten}");
+        // fields left at their defaults aren't written back out
+        assert!(!rewritten.contains("enabled"), "{rewritten}");
+        assert!(!rewritten.contains("new_tab"), "{rewritten}");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let desc = "a".repeat(crate::limits::max_body_bytes_from_env());
+        let body =
+            format!(r#"{{"name": "zz oversized", "url": "http://big.local", "desc": "{desc}"}}"#);
+        let res = post("application/json", &body).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!test_support::cfg_dir().join("zz-oversized.json").exists());
+    }
+
+    #[tokio::test]
+    async fn bodies_are_read_in_their_content_type() {
+        let _cfg = test_support::lock_cfg().await;
+        for (content_type, body, file) in [
+            (
+                "application/json; charset=utf-8",
+                r#"{"name": "zz json", "url": "http://json.local"}"#,
+                "zz-json.json",
+            ),
+            (
+                "application/yaml",
+                "name: zz yaml\nurl: http://yaml.local\n",
+                "zz-yaml.yaml",
+            ),
+            (
+                "application/toml",
+                "name = \"zz toml\"\nurl = \"http://toml.local\"\n",
+                "zz-toml.toml",
+            ),
+        ] {
+            let res = post(content_type, body).await;
+            assert_eq!(res.status(), StatusCode::CREATED, "{content_type}");
+            let path = test_support::cfg_dir().join(file);
+            assert!(path.exists(), "{content_type}");
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_or_malformed_bodies_are_rejected() {
+        let body = r#"{"name": "zz text", "url": "http://text.local"}"#;
+        let res = post("text/plain", body).await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let res = post("application/yaml", "name: [unclosed").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(test_support::text(res).await.contains("invalid yaml body"));
+    }
+
+    #[tokio::test]
+    async fn env_var_urls_are_checked_expanded_and_stored_as_is() {
+        let _cfg = test_support::lock_cfg().await;
+        std::env::set_var("HOME_SERVICES_TEST_API_HOST", "http://api.local");
+        let body = r#"{"name": "zz env", "url": "${HOME_SERVICES_TEST_API_HOST}/app"}"#;
+        let res = post("application/json", body).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let path = test_support::cfg_dir().join("zz-env.json");
+        let stored = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            stored.contains("${HOME_SERVICES_TEST_API_HOST}/app"),
+            "{stored}"
+        );
+
+        let unset = r#"{"name": "zz unset", "url": "${HOME_SERVICES_TEST_API_UNSET}/app"}"#;
+        let res = post("application/json", unset).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::OnceLock;
+
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::headers::{authorization::Basic, Authorization, HeaderMapExt};
+
+pub const ENV_VAR_AUTH_USER: &str = "HOME_SERVICE_AUTH_USER";
+pub const ENV_VAR_AUTH_PASS: &str = "HOME_SERVICE_AUTH_PASS";
+pub const ENV_VAR_AUTH_EXEMPT: &str = "HOME_SERVICE_AUTH_EXEMPT";
+const DEFAULT_EXEMPT: &str = "/metrics";
+
+static AUTH: OnceLock<Option<AuthConfig>> = OnceLock::new();
+
+#[derive(Debug)]
+struct AuthConfig {
+    user: String,
+    pass: String,
+    /// Paths that never require credentials
+    exempt: Vec<String>,
+}
+
+/// Enable basic auth when both the user and password env vars are set
+pub fn init_from_env() {
+    let user = std::env::var(ENV_VAR_AUTH_USER).ok();
+    let pass = std::env::var(ENV_VAR_AUTH_PASS).ok();
+    let cfg = match (user, pass) {
+        (Some(user), Some(pass)) => {
+            let exempt = std::env::var(ENV_VAR_AUTH_EXEMPT)
+                .unwrap_or_else(|_| DEFAULT_EXEMPT.to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect();
+            tracing::info!("basic auth enabled, exempt paths: {exempt:?}");
+            Some(AuthConfig { user, pass, exempt })
+        }
+        (None, None) => None,
+        _ => {
+            tracing::warn!(
+                "both {ENV_VAR_AUTH_USER} and {ENV_VAR_AUTH_PASS} are required to enable basic auth"
+            );
+            None
+        }
+    };
+    AUTH.set(cfg).unwrap();
+}
+
+/// Whether requests need credentials, for routes that are only safe to serve behind auth
+pub fn is_enabled() -> bool {
+    AUTH.get().is_some_and(Option::is_some)
+}
+
+/// Middleware rejecting requests without the configured credentials, a no-op
+/// when auth isn't enabled
+pub async fn require_basic_auth(req: Request, next: Next) -> Response {
+    match AUTH.get().and_then(Option::as_ref) {
+        Some(cfg) => authorize(cfg, req, next).await,
+        None => next.run(req).await,
+    }
+}
+
+async fn authorize(cfg: &AuthConfig, req: Request, next: Next) -> Response {
+    if cfg.exempt.iter().any(|p| p == req.uri().path()) {
+        return next.run(req).await;
+    }
+    let authorized = req
+        .headers()
+        .typed_get::<Authorization<Basic>>()
+        .map(|creds| {
+            // evaluate both so a wrong user takes as long as a wrong password
+            let user = constant_time_eq(creds.username(), &cfg.user);
+            let pass = constant_time_eq(creds.password(), &cfg.pass);
+            user & pass
+        })
+        .unwrap_or(false);
+    if authorized {
+        return next.run(req).await;
+    }
+    tracing::debug!("rejecting unauthorized request for `{}`", req.uri());
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            r#"Basic realm="Home Services", charset="UTF-8""#,
+        )],
+        "unauthorized",
+    )
+        .into_response()
+}
+
+fn constant_time_eq(lhs: &str, rhs: &str) -> bool {
+    let (lhs, rhs) = (lhs.as_bytes(), rhs.as_bytes());
+    if lhs.len() != rhs.len() {
+        return false;
+    }
+    lhs.iter().zip(rhs).fold(0u8, |acc, (l, r)| acc | (l ^ r)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::HeaderValue, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app() -> Router {
+        let cfg = Arc::new(AuthConfig {
+            user: "admin".to_string(),
+            pass: "hunter2".to_string(),
+            exempt: vec![DEFAULT_EXEMPT.to_string()],
+        });
+        Router::new()
+            .route("/", axum::routing::get(|| async { "ok" }))
+            .route("/metrics", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(move |req, next| {
+                let cfg = cfg.clone();
+                async move { authorize(&cfg, req, next).await }
+            }))
+    }
+
+    async fn request(uri: &str, authorization: Option<&str>) -> Response {
+        let mut req = Request::get(uri).body(Body::empty()).unwrap();
+        if let Some(value) = authorization {
+            req.headers_mut()
+                .insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        }
+        app().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_credentials_are_rejected() {
+        let res = request("/", None).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers()[header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap()
+            .starts_with("Basic realm="));
+    }
+
+    #[tokio::test]
+    async fn wrong_credentials_are_rejected() {
+        // admin:wrong
+        let res = request("/", Some("Basic YWRtaW46d3Jvbmc=")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn correct_credentials_are_accepted() {
+        // admin:hunter2
+        let res = request("/", Some("Basic YWRtaW46aHVudGVyMg==")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn exempt_paths_need_no_credentials() {
+        assert_eq!(request("/metrics", None).await.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn constant_time_eq_compares_contents() {
+        assert!(constant_time_eq("abc", "abc"));
+        assert!(!constant_time_eq("abc", "abd"));
+        assert!(!constant_time_eq("abc", "abcd"));
+    }
+}
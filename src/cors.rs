@@ -0,0 +1,116 @@
+use axum::http::{header, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+pub const ENV_VAR_CORS_ORIGINS: &str = "HOME_SERVICE_CORS_ORIGINS";
+
+/// `None` when no origins are configured, browsers then only allow same
+/// origin requests. `*` allows any origin but never with credentials.
+pub fn layer_from_env() -> Option<CorsLayer> {
+    parse_layer(std::env::var(ENV_VAR_CORS_ORIGINS).ok().as_deref())
+}
+
+fn parse_layer(value: Option<&str>) -> Option<CorsLayer> {
+    let value = value?.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let layer = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
+    if value == "*" {
+        tracing::info!("allowing cross origin api requests from any origin");
+        return Some(layer.allow_origin(AllowOrigin::any()));
+    }
+    let origins: Vec<HeaderValue> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(origin) => Some(origin),
+            Err(e) => {
+                tracing::warn!("ignoring invalid {ENV_VAR_CORS_ORIGINS} entry `{origin}`: {e}");
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        return None;
+    }
+    tracing::info!("allowing cross origin api requests from {origins:?}");
+    Some(
+        layer
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_credentials(true),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, http::StatusCode, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn send(origins: &str, req: Request) -> axum::response::Response {
+        Router::new()
+            .route("/api/services", axum::routing::get(|| async { "[]" }))
+            .layer(parse_layer(Some(origins)).unwrap())
+            .oneshot(req)
+            .await
+            .unwrap()
+    }
+
+    fn from(origin: &str) -> Request {
+        Request::get("/api/services")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn listed_origins_are_allowed() {
+        let origins = "http://dev.local:5173, http://other.local";
+        let res = send(origins, from("http://dev.local:5173")).await;
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://dev.local:5173"
+        );
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+        let res = send(origins, from("http://evil.local")).await;
+        assert!(res
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn preflight_requests_are_answered() {
+        let req = Request::options("/api/services")
+            .header(header::ORIGIN, "http://dev.local:5173")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+            .body(Body::empty())
+            .unwrap();
+        let res = send("http://dev.local:5173", req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let methods = res.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        assert!(methods.contains("DELETE"), "{methods}");
+    }
+
+    #[tokio::test]
+    async fn a_wildcard_allows_any_origin() {
+        let res = send("*", from("http://anywhere.local")).await;
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn no_origins_means_same_origin_only() {
+        assert!(parse_layer(None).is_none());
+        assert!(parse_layer(Some(" ")).is_none());
+        assert!(parse_layer(Some(" , ")).is_none());
+    }
+}
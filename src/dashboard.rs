@@ -0,0 +1,291 @@
+use std::{
+    cmp::Ordering,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+use serde::Deserialize;
+
+use crate::{escape_html, read_capped_text, read_cfg_text, validate_url, Service};
+
+/// Reserved file name in the root of the cfg dir for dashboard wide settings,
+/// it is never parsed as a service
+pub const DASHBOARD_FILE: &str = "dashboard.toml";
+const DEFAULT_TITLE: &str = "Home Services";
+const MAX_CUSTOM_CSS_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DashboardConfig {
+    pub title: String,
+    /// A url or path under /assets for an image shown beside the title
+    pub logo: Option<String>,
+    /// Any css color to use in place of the stylesheet's accent color
+    pub accent_color: Option<String>,
+    /// Categories to show first, in this order, any others follow alphabetically
+    pub category_order: Vec<String>,
+    pub sort_mode: SortMode,
+    pub group_by: GroupBy,
+    /// A css file relative to the cfg dir that is inlined into the page
+    pub custom_css_path: Option<PathBuf>,
+    /// A stylesheet linked from the page, the default content security
+    /// policy only allows ones served by this site
+    pub custom_css_url: Option<String>,
+    /// The contents of `custom_css_path`, read along with this file
+    #[serde(skip)]
+    pub custom_css: Option<String>,
+}
+
+/// What the services list is split into sections by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// Each service's `category`, when any of them have one
+    #[default]
+    Category,
+    /// The host of each service's url, relative urls are grouped as "Local"
+    Host,
+}
+
+/// How services are ordered within the list or a category
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    /// By `sort` and then by name
+    #[default]
+    Alpha,
+    /// By `sort` and then by name, comparing runs of digits as numbers so
+    /// `node-2` comes before `node-10`
+    Natural,
+    /// By `sort` alone, services with the same value stay in cfg file order
+    Manual,
+}
+
+impl SortMode {
+    pub fn compare(self, lhs: &Service, rhs: &Service) -> Ordering {
+        let by_sort = lhs.sort.unwrap_or(0).cmp(&rhs.sort.unwrap_or(0));
+        match self {
+            Self::Alpha => lhs.sort_key().cmp(&rhs.sort_key()),
+            Self::Natural => by_sort.then_with(|| natural_cmp(&lhs.name, &rhs.name)),
+            Self::Manual => by_sort,
+        }
+    }
+}
+
+/// Compare `lhs` and `rhs` ignoring case, with runs of digits compared by
+/// their numeric value
+fn natural_cmp(lhs: &str, rhs: &str) -> Ordering {
+    let (mut l, mut r) = (lhs.chars().peekable(), rhs.chars().peekable());
+    loop {
+        let ord = match (l.peek().copied(), r.peek().copied()) {
+            (None, None) => return lhs.cmp(rhs),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(lc), Some(rc)) if lc.is_ascii_digit() && rc.is_ascii_digit() => {
+                let (ln, rn) = (take_digits(&mut l), take_digits(&mut r));
+                ln.len().cmp(&rn.len()).then_with(|| ln.cmp(&rn))
+            }
+            (Some(lc), Some(rc)) => {
+                l.next();
+                r.next();
+                lc.to_lowercase().cmp(rc.to_lowercase())
+            }
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// The next run of digits without any leading zeros
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut ret = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        if !(ret.is_empty() && c == '0') {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_TITLE.to_string(),
+            logo: None,
+            accent_color: None,
+            category_order: Vec::new(),
+            sort_mode: SortMode::default(),
+            group_by: GroupBy::default(),
+            custom_css_path: None,
+            custom_css_url: None,
+            custom_css: None,
+        }
+    }
+}
+
+impl DashboardConfig {
+    pub async fn read(path: &Path) -> Result<Self, String> {
+        let s = read_cfg_text(path).await?;
+        let mut cfg: Self = toml::from_str(&s).map_err(|e| e.to_string())?;
+        if let (Some(css_path), Some(cfg_dir)) = (cfg.custom_css_path.as_deref(), path.parent()) {
+            match read_custom_css(cfg_dir, css_path).await {
+                Ok(css) => cfg.custom_css = Some(css),
+                Err(e) => tracing::warn!("ignoring custom_css_path `{}`: {e}", css_path.display()),
+            }
+        }
+        Ok(cfg)
+    }
+
+    pub fn title_html(&self) -> String {
+        escape_html(&self.title)
+    }
+
+    pub fn logo_html(&self) -> String {
+        let Some(logo) = self.logo.as_deref() else {
+            return String::new();
+        };
+        format!(
+            r#"<img class="dashboard-logo" src="{}" alt="" />"#,
+            escape_html(logo)
+        )
+    }
+
+    pub fn style_html(&self) -> String {
+        let mut ret = String::new();
+        match self.accent_color.as_deref() {
+            Some(color) if is_safe_css_color(color) => ret.push_str(&format!(
+                "<style>:root, body[data-theme] {{ --accent: {color}; --accent-hover: {color}; }}</style>"
+            )),
+            Some(color) => tracing::warn!("ignoring invalid accent_color `{color}`"),
+            None => {}
+        }
+        if let Some(css) = self.custom_css.as_deref() {
+            ret.push_str(&format!("<style>{css}</style>"));
+        }
+        match self
+            .custom_css_url
+            .as_deref()
+            .map(|url| (url, validate_url(url)))
+        {
+            Some((url, Ok(()))) => ret.push_str(&format!(
+                r#"<link rel="stylesheet" href="{}" />"#,
+                escape_html(url)
+            )),
+            Some((url, Err(e))) => tracing::warn!("ignoring custom_css_url `{url}`: {e}"),
+            None => {}
+        }
+        ret
+    }
+}
+
+/// Read `css_path` as long as it is inside of `cfg_dir`, no larger than
+/// [`MAX_CUSTOM_CSS_BYTES`] and can't close the `<style>` it is placed in
+async fn read_custom_css(cfg_dir: &Path, css_path: &Path) -> Result<String, String> {
+    let cfg_dir = tokio::fs::canonicalize(cfg_dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    let full = tokio::fs::canonicalize(cfg_dir.join(css_path))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !full.starts_with(&cfg_dir) {
+        return Err(format!("it is outside of `{}`", cfg_dir.display()));
+    }
+    let css = read_capped_text(&full, MAX_CUSTOM_CSS_BYTES).await?;
+    if css.contains("</") {
+        return Err("it contains `</`".to_string());
+    }
+    Ok(css)
+}
+
+/// A hex color like `#0d47a1` or a named color like `rebeccapurple`, anything
+/// else could be used to inject arbitrary css
+pub fn is_safe_css_color(color: &str) -> bool {
+    if let Some(hex) = color.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    !color.is_empty() && color.len() <= 32 && color.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, sort: Option<i32>) -> Service {
+        let mut service: Service =
+            toml::from_str(&format!("name = \"{name}\"\nurl = \"/\"")).unwrap();
+        service.sort = sort;
+        service
+    }
+
+    fn sorted(mode: SortMode, services: &[Service]) -> Vec<&str> {
+        let mut sorted: Vec<&Service> = services.iter().collect();
+        sorted.sort_by(|lhs, rhs| mode.compare(lhs, rhs));
+        sorted.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn natural_sort_compares_numbers_by_value() {
+        let services = [service("node-10", None), service("node-2", None)];
+        assert_eq!(sorted(SortMode::Natural, &services), ["node-2", "node-10"]);
+        assert_eq!(sorted(SortMode::Alpha, &services), ["node-10", "node-2"]);
+        assert_eq!(natural_cmp("Node-02", "node-2"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "A1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn manual_sort_keeps_cfg_order_for_ties() {
+        let services = [
+            service("c", Some(1)),
+            service("b", None),
+            service("a", None),
+        ];
+        assert_eq!(sorted(SortMode::Manual, &services), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn sort_mode_is_read_from_the_dashboard_file() {
+        let cfg: DashboardConfig = toml::from_str("sort_mode = \"natural\"").unwrap();
+        assert_eq!(cfg.sort_mode, SortMode::Natural);
+        assert_eq!(DashboardConfig::default().sort_mode, SortMode::Alpha);
+    }
+
+    async fn read_with_css(css_path: &str, css: &str) -> DashboardConfig {
+        let root = tempfile::tempdir().unwrap();
+        let cfg_dir = root.path().join("cfg");
+        std::fs::create_dir_all(cfg_dir.join("styles")).unwrap();
+        std::fs::write(cfg_dir.join("styles/custom.css"), css).unwrap();
+        std::fs::write(root.path().join("outside.css"), "body { color: red; }").unwrap();
+        let dashboard = cfg_dir.join(DASHBOARD_FILE);
+        std::fs::write(
+            &dashboard,
+            format!("custom_css_path = \"{css_path}\"\ncustom_css_url = \"/assets/extra.css\"\n"),
+        )
+        .unwrap();
+        DashboardConfig::read(&dashboard).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn custom_css_is_inlined_into_a_style_tag() {
+        let cfg = read_with_css("styles/custom.css", ".service { gap: 2rem; }").await;
+        let html = cfg.style_html();
+        assert!(
+            html.contains("<style>.service { gap: 2rem; }</style>"),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<link rel="stylesheet" href="/assets/extra.css" />"#),
+            "{html}"
+        );
+    }
+
+    #[tokio::test]
+    async fn unsafe_custom_css_is_ignored() {
+        let outside = read_with_css("../outside.css", "").await;
+        assert_eq!(outside.custom_css, None);
+        let closing = read_with_css("styles/custom.css", "</style><script>alert(1)</script>").await;
+        assert_eq!(closing.custom_css, None);
+        assert!(!closing.style_html().contains("<script>"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{LazyLock, PoisonError, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{read_cfg, Service, Services};
+
+pub const ENV_VAR_FETCH_FAVICONS: &str = "HOME_SERVICE_FETCH_FAVICONS";
+/// How often to look for services that haven't had their favicon fetched yet
+const SCAN_INTERVAL: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Favicons past this are dropped rather than kept in memory
+const MAX_FAVICON_BYTES: usize = 256 * 1024;
+/// Only raster formats are cached, an svg can carry scripts that would run on
+/// this origin if opened directly
+const RASTER_TYPES: &[&str] = &[
+    "image/x-icon",
+    "image/vnd.microsoft.icon",
+    "image/png",
+    "image/gif",
+    "image/jpeg",
+    "image/webp",
+];
+
+/// Fetched favicons keyed by [`origin_key`], `None` when the fetch failed so
+/// each origin is only tried once
+static FAVICONS: LazyLock<RwLock<HashMap<String, Option<Favicon>>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug, Clone)]
+struct Favicon {
+    content_type: String,
+    bytes: Vec<u8>,
+}
+
+pub fn enabled_from_env() -> bool {
+    crate::flag_from_env(ENV_VAR_FETCH_FAVICONS)
+}
+
+/// A stable, path safe name for the origin of `service`'s url, `None` when
+/// the url isn't absolute
+fn origin_key(service: &Service) -> Option<(String, reqwest::Url)> {
+    let url = reqwest::Url::parse(&service.url).ok()?;
+    if !url.origin().is_tuple() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    url.origin().ascii_serialization().hash(&mut hasher);
+    Some((format!("{:016x}", hasher.finish()), url))
+}
+
+/// Point every service without an `icon` at its cached favicon, if one
+/// has been fetched
+pub fn apply(services: &mut Services) {
+    let favicons = FAVICONS.read().unwrap_or_else(PoisonError::into_inner);
+    if favicons.is_empty() {
+        return;
+    }
+    for service in services.services.iter_mut().filter(|s| s.icon.is_none()) {
+        let Some((key, _)) = origin_key(service) else {
+            continue;
+        };
+        if let Some(Some(_)) = favicons.get(&key) {
+            service.icon = Some(format!("/favicons/{key}"));
+        }
+    }
+}
+
+pub async fn run() {
+    let client = match reqwest::Client::builder().timeout(FETCH_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("failed to build favicon client: {e}");
+            return;
+        }
+    };
+    let mut ticker = tokio::time::interval(SCAN_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let cfg = match read_cfg().await {
+            Ok(cfg) => cfg.without_disabled(),
+            Err(e) => {
+                tracing::warn!("skipping favicon fetch, failed to read cfg: {e}");
+                continue;
+            }
+        };
+        let pending: HashMap<String, reqwest::Url> = {
+            let favicons = FAVICONS.read().unwrap_or_else(PoisonError::into_inner);
+            cfg.services
+                .iter()
+                .filter(|s| s.icon.is_none())
+                .filter_map(origin_key)
+                .filter(|(key, _)| !favicons.contains_key(key))
+                .collect()
+        };
+        for (key, url) in pending {
+            let favicon = fetch(&client, &url).await;
+            FAVICONS
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key, favicon);
+            crate::invalidate_cfg_html();
+        }
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &reqwest::Url) -> Option<Favicon> {
+    let url = url.join("/favicon.ico").ok()?;
+    let res = match client.get(url.clone()).send().await {
+        Ok(res) if res.status().is_success() => res,
+        Ok(res) => {
+            tracing::debug!("no favicon at `{url}`: {}", res.status());
+            return None;
+        }
+        Err(e) => {
+            tracing::debug!("failed to fetch `{url}`: {e}");
+            return None;
+        }
+    };
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(raster_type)
+        .unwrap_or(Some("image/x-icon"));
+    // plenty of apps answer any path with their index page
+    let Some(content_type) = content_type else {
+        tracing::debug!("ignoring `{url}`, it isn't a raster image");
+        return None;
+    };
+    match read_capped(res).await {
+        Ok(bytes) if !bytes.is_empty() => {
+            tracing::debug!("cached `{url}`");
+            Some(Favicon {
+                content_type: content_type.to_string(),
+                bytes,
+            })
+        }
+        Ok(_) => None,
+        Err(e) => {
+            tracing::debug!("failed to read `{url}`: {e}");
+            None
+        }
+    }
+}
+
+/// The media type of `content_type` when it's one of [`RASTER_TYPES`],
+/// ignoring any parameters
+fn raster_type(content_type: &str) -> Option<&'static str> {
+    let media_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    RASTER_TYPES.iter().copied().find(|t| *t == media_type)
+}
+
+/// The body of `res`, giving up once it's past [`MAX_FAVICON_BYTES`] instead
+/// of reading the rest
+async fn read_capped(mut res: reqwest::Response) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = res.chunk().await.map_err(|e| e.to_string())? {
+        if bytes.len() + chunk.len() > MAX_FAVICON_BYTES {
+            return Err(format!("larger than {MAX_FAVICON_BYTES} bytes"));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+pub async fn serve(Path(key): Path<String>) -> Response {
+    let favicon = FAVICONS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key)
+        .cloned()
+        .flatten();
+    let Some(favicon) = favicon else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // the bytes came from another server, keep a browser from treating them
+    // as anything but an image
+    (
+        [
+            (header::CONTENT_TYPE, favicon.content_type),
+            (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        ],
+        favicon.bytes,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Router};
+
+    use super::*;
+    use crate::test_support;
+
+    /// The url of a server answering `/favicon.ico` with `bytes` as `content_type`
+    async fn server(content_type: &'static str, bytes: Vec<u8>) -> reqwest::Url {
+        let app = Router::new().route(
+            "/favicon.ico",
+            get(move || async move { ([(header::CONTENT_TYPE, content_type)], bytes) }),
+        );
+        let addr = test_support::serve(app).await;
+        format!("http://{addr}/app/").parse().unwrap()
+    }
+
+    async fn fetch_from(content_type: &'static str, bytes: Vec<u8>) -> Option<Favicon> {
+        fetch(&reqwest::Client::new(), &server(content_type, bytes).await).await
+    }
+
+    #[tokio::test]
+    async fn fetched_favicons_become_the_icon() {
+        let url = server("image/png; charset=binary", b"\x89PNG".to_vec()).await;
+        let mut services: Services = toml::from_str(&format!(
+            "[[service]]\nname = \"a\"\nurl = \"{url}\"\n\n\
+             [[service]]\nname = \"b\"\nurl = \"{url}\"\nicon = \"/assets/b.png\"\n"
+        ))
+        .unwrap();
+        let (key, origin) = origin_key(&services.services[0]).unwrap();
+        let favicon = fetch(&reqwest::Client::new(), &origin).await.unwrap();
+        assert_eq!(favicon.content_type, "image/png");
+        assert_eq!(favicon.bytes, b"\x89PNG");
+        FAVICONS
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key.clone(), Some(favicon));
+        apply(&mut services);
+        assert_eq!(
+            services.services[0].icon.as_deref(),
+            Some(format!("/favicons/{key}").as_str())
+        );
+        assert_eq!(services.services[1].icon.as_deref(), Some("/assets/b.png"));
+
+        let res = serve(Path(key)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(res.headers()[header::CONTENT_SECURITY_POLICY], "sandbox");
+    }
+
+    #[tokio::test]
+    async fn only_small_raster_favicons_are_kept() {
+        assert!(fetch_from("image/x-icon", vec![1; 16]).await.is_some());
+        assert!(fetch_from("image/svg+xml", b"<svg/>".to_vec())
+            .await
+            .is_none());
+        assert!(fetch_from("text/html", b"<html>".to_vec()).await.is_none());
+        assert!(fetch_from("image/x-icon", vec![0; MAX_FAVICON_BYTES + 1])
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn raster_types_ignore_case_and_parameters() {
+        assert_eq!(raster_type("Image/PNG; q=1"), Some("image/png"));
+        assert_eq!(raster_type("image/svg+xml"), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_favicons_are_not_found() {
+        let res = serve(Path("missing".to_string())).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
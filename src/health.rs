@@ -0,0 +1,1065 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, PoisonError, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::{alert::Alerter, is_default, read_cfg, Service};
+
+pub const ENV_VAR_HEALTH_INTERVAL: &str = "HOME_SERVICE_HEALTH_INTERVAL_SECS";
+pub const ENV_VAR_HEALTH_CONCURRENCY: &str = "HOME_SERVICE_HEALTH_CONCURRENCY";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+pub const ENV_VAR_STATE_DIR: &str = "HOME_SERVICE_STATE_DIR";
+const DEFAULT_CONCURRENCY: usize = 8;
+const STATE_FILE: &str = "health.json";
+/// Saved statuses older than this are left as unknown at startup
+const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const SLOW_AFTER: Duration = Duration::from_millis(1000);
+/// How many of the latest results are kept for each service's sparkline
+const HISTORY_LEN: usize = 30;
+
+static STATUSES: LazyLock<RwLock<HashMap<String, Status>>> = LazyLock::new(Default::default);
+/// When each service was last checked and what it responded with
+static LAST_CHECKS: LazyLock<RwLock<HashMap<String, LastCheck>>> = LazyLock::new(Default::default);
+/// Set once checks start running so the legend is only shown when it means something
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The latest results for each service, oldest first
+static HISTORY: LazyLock<RwLock<HashMap<String, VecDeque<Status>>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    /// Responded as expected but slower than the check's `slow_ms`
+    Degraded,
+    Down,
+}
+
+impl Status {
+    pub fn css_class(status: Option<Self>) -> &'static str {
+        match status {
+            Some(Self::Up) => "status-up",
+            Some(Self::Degraded) => "status-degraded",
+            Some(Self::Down) => "status-down",
+            None => "status-unknown",
+        }
+    }
+
+    /// Up or degraded, the service is reachable either way
+    pub fn is_up(self) -> bool {
+        matches!(self, Self::Up | Self::Degraded)
+    }
+
+    pub fn label(status: Option<Self>) -> &'static str {
+        match status {
+            Some(Self::Up) => "up",
+            Some(Self::Degraded) => "degraded",
+            Some(Self::Down) => "down",
+            None => "unknown",
+        }
+    }
+}
+
+/// The result of checking a service once
+struct Checked {
+    status: Status,
+    /// What the service responded with, for the status dot's tooltip
+    detail: String,
+}
+
+#[derive(Debug, Clone)]
+struct LastCheck {
+    at: SystemTime,
+    detail: String,
+}
+
+/// How to check a single service, every field is optional and only the ones
+/// that aren't the default are written back out
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthCheck {
+    #[serde(skip_serializing_if = "is_default")]
+    pub check: CheckKind,
+    /// Resolved against the service's url, defaults to the url itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// For tcp checks, defaults to the url's host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// For tcp checks, defaults to the url's port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Successful checks slower than this are degraded, defaults to 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slow_ms: Option<u64>,
+    /// Status codes that mean the service is up, defaults to any 2xx
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expect_status: Vec<u16>,
+    #[serde(skip_serializing_if = "is_default")]
+    pub method: HealthMethod,
+    /// Defaults to true, when false a redirect is judged by its own status
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
+    /// Sent with http checks, values support `${VAR}` expansion
+    #[serde(skip_serializing_if = "is_default")]
+    pub headers: HealthHeaders,
+}
+
+/// Extra request headers, values of any that look like credentials are
+/// redacted from `Debug` so they never end up in the logs
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct HealthHeaders(pub HashMap<String, String>);
+
+impl std::fmt::Debug for HealthHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(name, value)| {
+                let value = if is_sensitive_header(name) {
+                    "<redacted>"
+                } else {
+                    value.as_str()
+                };
+                (name, value)
+            }))
+            .finish()
+    }
+}
+
+fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [
+        "auth", "cookie", "token", "key", "secret", "session", "pass",
+    ]
+    .iter()
+    .any(|word| name.contains(word))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckKind {
+    /// Make a request and check the response status
+    #[default]
+    Http,
+    /// Only check that a connection can be opened, for services that don't speak http
+    Tcp,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthMethod {
+    #[default]
+    Get,
+    Head,
+}
+
+impl HealthCheck {
+    fn timeout(&self) -> Duration {
+        self.timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(CHECK_TIMEOUT)
+    }
+
+    /// Up, or degraded when getting there took longer than `slow_ms`
+    fn up_status(&self, elapsed: Duration) -> Status {
+        let slow_after = self
+            .slow_ms
+            .map(Duration::from_millis)
+            .unwrap_or(SLOW_AFTER);
+        if elapsed > slow_after {
+            Status::Degraded
+        } else {
+            Status::Up
+        }
+    }
+
+    fn is_expected(&self, status: reqwest::StatusCode) -> bool {
+        if self.expect_status.is_empty() {
+            return status.is_success();
+        }
+        self.expect_status.contains(&status.as_u16())
+    }
+}
+
+impl From<HealthMethod> for reqwest::Method {
+    fn from(method: HealthMethod) -> Self {
+        match method {
+            HealthMethod::Get => Self::GET,
+            HealthMethod::Head => Self::HEAD,
+        }
+    }
+}
+
+/// The latest recorded status for the service named `name`, `None` if it
+/// hasn't been checked yet
+pub fn status_of(name: &str) -> Option<Status> {
+    STATUSES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(name)
+        .copied()
+}
+
+/// The tooltip for the status dot of the service named `name`, with when it
+/// was last checked and what it responded with once that is known
+pub fn status_title(name: &str, status: Option<Status>) -> String {
+    let checks = LAST_CHECKS.read().unwrap_or_else(PoisonError::into_inner);
+    title(status, checks.get(name))
+}
+
+fn title(status: Option<Status>, last: Option<&LastCheck>) -> String {
+    let label = Status::label(status);
+    match last {
+        Some(last) => format!(
+            "{label}, checked {}: {}",
+            humantime::format_rfc3339_seconds(last.at),
+            last.detail
+        ),
+        None => label.to_string(),
+    }
+}
+
+/// What each status dot color means, empty when health checks are disabled
+pub fn legend_html() -> String {
+    legend(ENABLED.load(Ordering::Relaxed))
+}
+
+fn legend(enabled: bool) -> String {
+    if !enabled {
+        return String::new();
+    }
+    let items: String = [
+        Some(Status::Up),
+        Some(Status::Degraded),
+        Some(Status::Down),
+        None,
+    ]
+        .into_iter()
+        .map(|status| {
+            let label = Status::label(status);
+            format!(
+                r#"<li><svg width="10" height="10" aria-hidden="true"><circle class="legend-{label}" cx="5" cy="5" r="5" /></svg>{label}</li>"#
+            )
+        })
+        .collect();
+    format!(r#"<ul class="status-legend" aria-label="Status legend">{items}</ul>"#)
+}
+
+/// An inline svg with a bar for each of the latest results for the service
+/// named `name`, empty until it has been checked
+pub fn sparkline_html(name: &str) -> String {
+    let history = HISTORY.read().unwrap_or_else(PoisonError::into_inner);
+    match history.get(name) {
+        Some(results) => sparkline(results),
+        None => String::new(),
+    }
+}
+
+fn sparkline(results: &VecDeque<Status>) -> String {
+    if results.is_empty() {
+        return String::new();
+    }
+    let bars: String = results
+        .iter()
+        .enumerate()
+        .map(|(i, status)| {
+            let class = match status {
+                Status::Up => "spark-up",
+                Status::Degraded => "spark-degraded",
+                Status::Down => "spark-down",
+            };
+            format!(
+                r#"<rect class="{class}" x="{}" y="0" width="2" height="8" />"#,
+                i * 3
+            )
+        })
+        .collect();
+    let up = results.iter().filter(|s| s.is_up()).count();
+    format!(
+        r#"<svg class="sparkline" width="{}" height="8" role="img" aria-label="up for {up} of the last {} checks">{bars}</svg>"#,
+        results.len() * 3,
+        results.len(),
+    )
+}
+
+/// Append the latest results, dropping the oldest past [`HISTORY_LEN`] and
+/// any services that are no longer configured
+fn record_history(statuses: &HashMap<String, Status>) {
+    push_history(
+        &mut HISTORY.write().unwrap_or_else(PoisonError::into_inner),
+        statuses,
+    );
+}
+
+fn push_history(
+    history: &mut HashMap<String, VecDeque<Status>>,
+    statuses: &HashMap<String, Status>,
+) {
+    history.retain(|name, _| statuses.contains_key(name));
+    for (name, status) in statuses {
+        let results = history.entry(name.clone()).or_default();
+        if results.len() == HISTORY_LEN {
+            results.pop_front();
+        }
+        results.push_back(*status);
+    }
+}
+
+/// A banner summarizing the status of `services`, services that haven't been
+/// checked yet don't count as down. Empty until at least one has been checked.
+pub fn summary_html(services: &[Service]) -> String {
+    summary(
+        services,
+        &STATUSES.read().unwrap_or_else(PoisonError::into_inner),
+    )
+}
+
+fn summary(services: &[Service], statuses: &HashMap<String, Status>) -> String {
+    let enabled = services.iter().filter(|s| s.enabled);
+    let total = enabled.clone().count();
+    let checked: Vec<Status> = enabled
+        .filter_map(|s| statuses.get(&s.name).copied())
+        .collect();
+    if checked.is_empty() {
+        return String::new();
+    }
+    let down = checked.iter().filter(|s| **s == Status::Down).count();
+    let degraded = checked.iter().filter(|s| **s == Status::Degraded).count();
+    let noun = if total == 1 { "service" } else { "services" };
+    let (class, text) = if down > 0 {
+        ("banner-down", format!("{down} of {total} {noun} down"))
+    } else if degraded > 0 {
+        (
+            "banner-degraded",
+            format!("{degraded} of {total} {noun} slow"),
+        )
+    } else {
+        ("banner-up", "All systems operational".to_string())
+    };
+    format!(r#"<div class="notice status-banner {class}" role="status">{text}</div>"#)
+}
+
+/// The latest statuses as saved to the state dir
+#[derive(Debug, Deserialize, Serialize)]
+struct Snapshot {
+    /// Seconds since the unix epoch
+    checked_at: u64,
+    statuses: HashMap<String, Status>,
+}
+
+/// `None` when no state dir is configured and statuses aren't persisted
+fn state_path() -> Option<PathBuf> {
+    let dir = std::env::var_os(ENV_VAR_STATE_DIR).filter(|dir| !dir.is_empty())?;
+    Some(PathBuf::from(dir).join(STATE_FILE))
+}
+
+/// Seed the statuses from the state dir so a restart doesn't reset every
+/// service to unknown, statuses older than [`STALE_AFTER`] are ignored
+pub async fn restore() {
+    let Some(path) = state_path() else {
+        return;
+    };
+    let Some(statuses) = load(&path, SystemTime::now()).await else {
+        return;
+    };
+    tracing::info!(
+        "restored {} status(es) from `{}`",
+        statuses.len(),
+        path.display()
+    );
+    *STATUSES.write().unwrap_or_else(PoisonError::into_inner) = statuses;
+    crate::invalidate_cfg_html();
+}
+
+/// The statuses saved at `path`, `None` when there aren't any or they were
+/// saved more than [`STALE_AFTER`] before `now`
+async fn load(path: &Path, now: SystemTime) -> Option<HashMap<String, Status>> {
+    let snapshot: Snapshot = match tokio::fs::read(path).await {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::warn!("ignoring invalid health state `{}`: {e}", path.display());
+                return None;
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            tracing::warn!("failed to read health state `{}`: {e}", path.display());
+            return None;
+        }
+    };
+    let checked_at = SystemTime::UNIX_EPOCH + Duration::from_secs(snapshot.checked_at);
+    let age = now.duration_since(checked_at).unwrap_or_default();
+    if age > STALE_AFTER {
+        tracing::debug!("health state is {age:?} old, not restoring it");
+        return None;
+    }
+    Some(snapshot.statuses)
+}
+
+/// Save the latest statuses to the state dir, replacing the file in one step
+/// so a crash mid write can't leave it truncated
+pub async fn persist() {
+    let Some(path) = state_path() else {
+        return;
+    };
+    let statuses = STATUSES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if statuses.is_empty() {
+        return;
+    }
+    save(&path, statuses, SystemTime::now()).await;
+}
+
+async fn save(path: &Path, statuses: HashMap<String, Status>, now: SystemTime) {
+    let checked_at = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let json = match serde_json::to_vec(&Snapshot {
+        checked_at,
+        statuses,
+    }) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::warn!("failed to serialize health state: {e}");
+            return;
+        }
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = tokio::fs::create_dir_all(dir).await {
+            tracing::warn!("failed to create state dir `{}`: {e}", dir.display());
+            return;
+        }
+    }
+    let tmp = path.with_extension("json.tmp");
+    let res = async {
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await
+    };
+    if let Err(e) = res.await {
+        tracing::warn!("failed to save health state `{}`: {e}", path.display());
+    }
+}
+
+/// How often to check each service, `None` when checks have been disabled
+/// by setting the interval to 0
+pub fn interval_from_env() -> Option<Duration> {
+    parse_interval(std::env::var(ENV_VAR_HEALTH_INTERVAL).ok().as_deref())
+}
+
+fn parse_interval(value: Option<&str>) -> Option<Duration> {
+    let Some(value) = value else {
+        return Some(DEFAULT_INTERVAL);
+    };
+    match value.trim().parse::<u64>() {
+        Ok(0) => None,
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_HEALTH_INTERVAL} `{value}`: {e}");
+            Some(DEFAULT_INTERVAL)
+        }
+    }
+}
+
+/// The most checks to run at once, 0 isn't allowed since nothing would ever
+/// be checked
+fn concurrency_from_env() -> usize {
+    parse_concurrency(std::env::var(ENV_VAR_HEALTH_CONCURRENCY).ok().as_deref())
+}
+
+fn parse_concurrency(value: Option<&str>) -> usize {
+    let Some(value) = value else {
+        return DEFAULT_CONCURRENCY;
+    };
+    match value.trim().parse::<usize>() {
+        Ok(0) => {
+            tracing::warn!(
+                "{ENV_VAR_HEALTH_CONCURRENCY} must be at least 1, using {DEFAULT_CONCURRENCY}"
+            );
+            DEFAULT_CONCURRENCY
+        }
+        Ok(limit) => limit,
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_HEALTH_CONCURRENCY} `{value}`: {e}");
+            DEFAULT_CONCURRENCY
+        }
+    }
+}
+
+/// Checks that follow redirects, the default, share `follow` with alerts and
+/// the rest use `no_follow`
+struct Clients {
+    follow: reqwest::Client,
+    no_follow: reqwest::Client,
+}
+
+impl Clients {
+    fn new() -> reqwest::Result<Self> {
+        Ok(Self {
+            follow: reqwest::Client::builder().build()?,
+            no_follow: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+        })
+    }
+
+    fn for_check(&self, check: &HealthCheck) -> &reqwest::Client {
+        if check.follow_redirects.unwrap_or(true) {
+            &self.follow
+        } else {
+            &self.no_follow
+        }
+    }
+}
+
+pub async fn run(interval: Duration) {
+    let clients = match Clients::new() {
+        Ok(clients) => clients,
+        Err(e) => {
+            tracing::error!("failed to build health check client: {e}");
+            return;
+        }
+    };
+    ENABLED.store(true, Ordering::Relaxed);
+    let mut alerter = Alerter::from_env();
+    let limit = Semaphore::new(concurrency_from_env());
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let cfg = match read_cfg().await {
+            Ok(cfg) => cfg.without_disabled(),
+            Err(e) => {
+                tracing::warn!("skipping health checks, failed to read cfg: {e}");
+                continue;
+            }
+        };
+        let results = check_all(&clients, &cfg.services, &limit).await;
+        let checked_at = SystemTime::now();
+        let checked: Vec<(String, Checked)> = cfg
+            .services
+            .iter()
+            .zip(results)
+            .filter_map(|(service, checked)| Some((service.name.clone(), checked?)))
+            .collect();
+        let statuses: HashMap<String, Status> = checked
+            .iter()
+            .map(|(name, checked)| (name.clone(), checked.status))
+            .collect();
+        *LAST_CHECKS.write().unwrap_or_else(PoisonError::into_inner) = checked
+            .into_iter()
+            .map(|(name, checked)| {
+                let last = LastCheck {
+                    at: checked_at,
+                    detail: checked.detail,
+                };
+                (name, last)
+            })
+            .collect();
+        record_history(&statuses);
+        let previous = std::mem::replace(
+            &mut *STATUSES.write().unwrap_or_else(PoisonError::into_inner),
+            statuses.clone(),
+        );
+        // the status dots and sparklines are part of the cached list
+        crate::invalidate_cfg_html();
+        persist().await;
+        if let Some(alerter) = alerter.as_mut() {
+            alerter
+                .notify(&clients.follow, &previous, &statuses, &cfg.services)
+                .await;
+        }
+    }
+}
+
+/// Check every one of `services`, with no more at once than `limit` has permits
+async fn check_all(
+    clients: &Clients,
+    services: &[Service],
+    limit: &Semaphore,
+) -> Vec<Option<Checked>> {
+    futures::future::join_all(services.iter().map(|service| async {
+        // the semaphore is never closed
+        let _permit = limit.acquire().await.ok();
+        check_service(clients, service).await
+    }))
+    .await
+}
+
+async fn check_service(clients: &Clients, service: &Service) -> Option<Checked> {
+    let default = HealthCheck::default();
+    let check = service.health.as_ref().unwrap_or(&default);
+    let base = reqwest::Url::parse(&service.url).ok();
+    match check.check {
+        CheckKind::Http => {
+            let Some(base) = base else {
+                tracing::trace!("not checking `{}`, url isn't absolute", service.name);
+                return None;
+            };
+            check_http(clients.for_check(check), service, check, base).await
+        }
+        CheckKind::Tcp => check_tcp(service, check, base.as_ref()).await,
+    }
+}
+
+async fn check_http(
+    client: &reqwest::Client,
+    service: &Service,
+    check: &HealthCheck,
+    base: reqwest::Url,
+) -> Option<Checked> {
+    let url = match check.path.as_deref() {
+        Some(path) => match base.join(path) {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::warn!(
+                    "not checking `{}`, invalid health path `{path}`: {e}",
+                    service.name
+                );
+                return None;
+            }
+        },
+        None => base,
+    };
+    let mut req = client
+        .request(check.method.into(), url)
+        .timeout(check.timeout());
+    if !check.headers.0.is_empty() {
+        tracing::trace!(
+            "checking `{}` with headers {:?}",
+            service.name,
+            check.headers
+        );
+    }
+    for (name, value) in &check.headers.0 {
+        req = req.header(name, value);
+    }
+    let start = Instant::now();
+    let res = match req.send().await {
+        Ok(res) => res,
+        Err(e) => {
+            tracing::debug!("`{}` is unreachable: {e}", service.name);
+            let detail = if e.is_timeout() {
+                "timed out"
+            } else {
+                "unreachable"
+            };
+            return Some(Checked {
+                status: Status::Down,
+                detail: detail.to_string(),
+            });
+        }
+    };
+    let elapsed = start.elapsed();
+    let status = if check.is_expected(res.status()) {
+        check.up_status(elapsed)
+    } else {
+        tracing::debug!("`{}` responded with {}", service.name, res.status());
+        Status::Down
+    };
+    Some(Checked {
+        status,
+        detail: format!("HTTP {} in {}ms", res.status(), elapsed.as_millis()),
+    })
+}
+
+async fn check_tcp(
+    service: &Service,
+    check: &HealthCheck,
+    base: Option<&reqwest::Url>,
+) -> Option<Checked> {
+    let host = check
+        .host
+        .clone()
+        .or_else(|| base?.host_str().map(String::from));
+    let port = check.port.or_else(|| base?.port_or_known_default());
+    let (Some(host), Some(port)) = (host, port) else {
+        tracing::trace!(
+            "not checking `{}`, no host and port to connect to",
+            service.name
+        );
+        return None;
+    };
+    let start = Instant::now();
+    let connect = tokio::net::TcpStream::connect((host.as_str(), port));
+    let (status, detail) = match tokio::time::timeout(check.timeout(), connect).await {
+        Ok(Ok(_)) => {
+            let elapsed = start.elapsed();
+            (
+                check.up_status(elapsed),
+                format!("connected to {host}:{port} in {}ms", elapsed.as_millis()),
+            )
+        }
+        Ok(Err(e)) => {
+            tracing::debug!(
+                "`{}` refused a connection to {host}:{port}: {e}",
+                service.name
+            );
+            (Status::Down, format!("connecting to {host}:{port} failed"))
+        }
+        Err(_) => {
+            tracing::debug!("`{}` timed out connecting to {host}:{port}", service.name);
+            (
+                Status::Down,
+                format!("timed out connecting to {host}:{port}"),
+            )
+        }
+    };
+    Some(Checked { status, detail })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, routing::get, Router};
+
+    use super::*;
+    use crate::test_support;
+
+    fn service(toml: &str) -> Service {
+        toml::from_str(toml).unwrap()
+    }
+
+    async fn check(toml: &str) -> Option<Checked> {
+        check_service(&Clients::new().unwrap(), &service(toml)).await
+    }
+
+    async fn app() -> std::net::SocketAddr {
+        test_support::serve(
+            Router::new()
+                .route("/", get(|| async { "ok" }))
+                .route(
+                    "/broken",
+                    get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+                )
+                .route("/login", get(|| async { StatusCode::UNAUTHORIZED }))
+                .route("/head", axum::routing::head(|| async { "ok" }))
+                .route(
+                    "/slow",
+                    get(|| async {
+                        tokio::time::sleep(Duration::from_millis(500)).await;
+                        "ok"
+                    }),
+                ),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn http_checks_report_up_and_down() {
+        let addr = app().await;
+        let up = check(&format!("name = \"a\"\nurl = \"http://{addr}/\"")).await;
+        assert_eq!(up.unwrap().status, Status::Up);
+        let broken = check(&format!(
+            "name = \"a\"\nurl = \"http://{addr}/\"\nhealth = {{ path = \"/broken\" }}"
+        ))
+        .await;
+        assert_eq!(broken.unwrap().status, Status::Down);
+        let closed = test_support::closed_addr().await;
+        let unreachable = check(&format!("name = \"a\"\nurl = \"http://{closed}/\"")).await;
+        assert_eq!(unreachable.unwrap().status, Status::Down);
+    }
+
+    #[tokio::test]
+    async fn relative_urls_are_not_checked() {
+        assert!(check("name = \"a\"\nurl = \"/grafana\"").await.is_none());
+    }
+
+    #[test]
+    fn statuses_have_a_badge_class() {
+        assert_eq!(Status::css_class(Some(Status::Up)), "status-up");
+        assert_eq!(Status::css_class(Some(Status::Down)), "status-down");
+        assert_eq!(Status::css_class(None), "status-unknown");
+    }
+
+    #[tokio::test]
+    async fn health_params_are_used() {
+        let addr = app().await;
+        let login =
+            |health: &str| format!("name = \"a\"\nurl = \"http://{addr}/\"\nhealth = {health}");
+        let expected_401 = check(&login(r#"{ path = "/login", expect_status = [401] }"#)).await;
+        assert_eq!(expected_401.unwrap().status, Status::Up);
+        let unexpected_401 = check(&login(r#"{ path = "/login" }"#)).await;
+        assert_eq!(unexpected_401.unwrap().status, Status::Down);
+        let head = check(&login(r#"{ path = "/head", method = "HEAD" }"#)).await;
+        assert_eq!(head.unwrap().status, Status::Up);
+        let get_only = check(&login(r#"{ path = "/head" }"#)).await;
+        assert_eq!(get_only.unwrap().status, Status::Down);
+        let timed_out = check(&login(r#"{ path = "/slow", timeout_ms = 50 }"#))
+            .await
+            .unwrap();
+        assert_eq!(timed_out.status, Status::Down);
+        assert_eq!(timed_out.detail, "timed out");
+    }
+
+    fn statuses(entries: &[(&str, Status)]) -> HashMap<String, Status> {
+        entries
+            .iter()
+            .map(|(name, status)| (name.to_string(), *status))
+            .collect()
+    }
+
+    #[test]
+    fn summary_reports_the_worst_state() {
+        let services: Vec<Service> = ["a", "b", "c"]
+            .iter()
+            .map(|name| service(&format!("name = \"{name}\"\nurl = \"/{name}\"")))
+            .collect();
+        assert_eq!(summary(&services, &HashMap::new()), "");
+        let all_up = summary(&services, &statuses(&[("a", Status::Up)]));
+        assert!(all_up.contains("banner-up"), "{all_up}");
+        assert!(all_up.contains("All systems operational"), "{all_up}");
+        let slow = summary(
+            &services,
+            &statuses(&[("a", Status::Up), ("b", Status::Degraded)]),
+        );
+        assert!(slow.contains("banner-degraded"), "{slow}");
+        assert!(slow.contains("1 of 3 services slow"), "{slow}");
+        let down = summary(
+            &services,
+            &statuses(&[
+                ("a", Status::Down),
+                ("b", Status::Degraded),
+                ("c", Status::Down),
+            ]),
+        );
+        assert!(down.contains("banner-down"), "{down}");
+        assert!(down.contains("2 of 3 services down"), "{down}");
+    }
+
+    #[tokio::test]
+    async fn tcp_checks_connect_to_the_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        let up = check(&format!(
+            "name = \"db\"\nurl = \"postgres://{open}/db\"\nhealth = {{ check = \"tcp\" }}"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(up.status, Status::Up);
+        assert!(up
+            .detail
+            .starts_with(&format!("connected to 127.0.0.1:{}", open.port())));
+        let closed = test_support::closed_addr().await;
+        let down = check(&format!(
+            "name = \"db\"\nurl = \"/db\"\nhealth = {{ check = \"tcp\", host = \"127.0.0.1\", port = {} }}",
+            closed.port()
+        ))
+        .await
+        .unwrap();
+        assert_eq!(down.status, Status::Down);
+        assert!(
+            check("name = \"db\"\nurl = \"/db\"\nhealth = { check = \"tcp\" }")
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn history_is_bounded_and_drops_removed_services() {
+        let mut history = HashMap::new();
+        push_history(&mut history, &statuses(&[("gone", Status::Up)]));
+        for i in 0..HISTORY_LEN + 5 {
+            let status = if i % 2 == 0 { Status::Up } else { Status::Down };
+            push_history(&mut history, &statuses(&[("a", status)]));
+        }
+        assert!(!history.contains_key("gone"));
+        assert_eq!(history["a"].len(), HISTORY_LEN);
+        // the oldest results were dropped
+        assert_eq!(history["a"][0], Status::Down);
+    }
+
+    #[test]
+    fn sparkline_has_a_bar_per_result() {
+        assert_eq!(sparkline(&VecDeque::new()), "");
+        let results = VecDeque::from([Status::Up, Status::Down, Status::Degraded, Status::Up]);
+        let svg = sparkline(&results);
+        assert_eq!(svg.matches("<rect").count(), 4, "{svg}");
+        assert_eq!(svg.matches("spark-up").count(), 2, "{svg}");
+        assert_eq!(svg.matches("spark-down").count(), 1, "{svg}");
+        assert_eq!(svg.matches("spark-degraded").count(), 1, "{svg}");
+        assert!(
+            svg.contains(r#"aria-label="up for 3 of the last 4 checks""#),
+            "{svg}"
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_are_limited_to_the_concurrency() {
+        use std::sync::{atomic::AtomicUsize, Arc};
+
+        #[derive(Clone, Default)]
+        struct InFlight {
+            now: Arc<AtomicUsize>,
+            max: Arc<AtomicUsize>,
+        }
+        let in_flight = InFlight::default();
+        let app = Router::new()
+            .route(
+                "/",
+                get(
+                    |axum::extract::State(in_flight): axum::extract::State<InFlight>| async move {
+                        let now = in_flight.now.fetch_add(1, Ordering::SeqCst) + 1;
+                        in_flight.max.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        in_flight.now.fetch_sub(1, Ordering::SeqCst);
+                    },
+                ),
+            )
+            .with_state(in_flight.clone());
+        let addr = test_support::serve(app).await;
+        let services: Vec<Service> = (0..10)
+            .map(|i| service(&format!("name = \"s{i}\"\nurl = \"http://{addr}/\"")))
+            .collect();
+        let results = check_all(&Clients::new().unwrap(), &services, &Semaphore::new(3)).await;
+        assert!(results
+            .iter()
+            .all(|r| r.as_ref().unwrap().status == Status::Up));
+        assert_eq!(in_flight.max.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn interval_and_concurrency_parsing() {
+        assert_eq!(parse_concurrency(Some("4")), 4);
+        assert_eq!(parse_concurrency(Some("0")), DEFAULT_CONCURRENCY);
+        assert_eq!(parse_concurrency(Some("many")), DEFAULT_CONCURRENCY);
+        assert_eq!(parse_concurrency(None), DEFAULT_CONCURRENCY);
+        assert_eq!(parse_interval(None), Some(DEFAULT_INTERVAL));
+        assert_eq!(parse_interval(Some(" 5 ")), Some(Duration::from_secs(5)));
+        assert_eq!(parse_interval(Some("0")), None);
+    }
+
+    #[tokio::test]
+    async fn saved_statuses_are_restored_until_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join(STATE_FILE);
+        let saved = statuses(&[("plex", Status::Up), ("grafana", Status::Down)]);
+        let now = SystemTime::now();
+        save(&path, saved.clone(), now).await;
+        assert!(!path.with_extension("json.tmp").exists());
+
+        assert_eq!(load(&path, now).await, Some(saved));
+        let later = now + STALE_AFTER + Duration::from_secs(1);
+        assert_eq!(load(&path, later).await, None);
+    }
+
+    #[tokio::test]
+    async fn missing_or_invalid_state_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        assert_eq!(load(&path, SystemTime::now()).await, None);
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(load(&path, SystemTime::now()).await, None);
+    }
+
+    #[tokio::test]
+    async fn redirects_are_only_up_when_followed() {
+        let addr = test_support::serve(Router::new().route("/", get(|| async { "ok" })).route(
+            "/moved",
+            get(|| async { (StatusCode::FOUND, [(axum::http::header::LOCATION, "/")]) }),
+        ))
+        .await;
+        let moved = |follow: &str| {
+            format!(
+                "name = \"a\"\nurl = \"http://{addr}/\"\nhealth = {{ path = \"/moved\"{follow} }}"
+            )
+        };
+        let followed = check(&moved(", follow_redirects = true")).await.unwrap();
+        assert_eq!(followed.status, Status::Up);
+        assert!(
+            followed.detail.starts_with("HTTP 200 OK"),
+            "{}",
+            followed.detail
+        );
+        let default = check(&moved("")).await.unwrap();
+        assert_eq!(default.status, Status::Up);
+        let not_followed = check(&moved(", follow_redirects = false")).await.unwrap();
+        assert_eq!(not_followed.status, Status::Down);
+        assert!(
+            not_followed.detail.starts_with("HTTP 302 Found"),
+            "{}",
+            not_followed.detail
+        );
+    }
+
+    #[test]
+    fn sensitive_header_values_are_redacted() {
+        let headers = HealthHeaders(HashMap::from([
+            ("Authorization".to_string(), "Bearer hunter2".to_string()),
+            ("X-Api-Key".to_string(), "hunter3".to_string()),
+            ("Accept".to_string(), "text/plain".to_string()),
+        ]));
+        let debug = format!("{headers:?}");
+        assert!(!debug.contains("hunter"), "{debug}");
+        assert!(
+            debug.contains(r#""Authorization": "<redacted>""#),
+            "{debug}"
+        );
+        assert!(debug.contains(r#""Accept": "text/plain""#), "{debug}");
+    }
+
+    #[test]
+    fn the_legend_is_only_shown_with_checks_enabled() {
+        assert_eq!(legend(false), "");
+        let html = legend(true);
+        assert!(
+            html.starts_with(r#"<ul class="status-legend" aria-label="Status legend">"#),
+            "{html}"
+        );
+        for label in ["up", "degraded", "down", "unknown"] {
+            assert!(
+                html.contains(&format!(
+                    r#"<circle class="legend-{label}" cx="5" cy="5" r="5" /></svg>{label}</li>"#
+                )),
+                "{html}"
+            );
+        }
+    }
+
+    #[test]
+    fn tooltips_show_the_last_check() {
+        assert_eq!(title(None, None), "unknown");
+        let last = LastCheck {
+            at: SystemTime::UNIX_EPOCH + Duration::from_secs(86400),
+            detail: "HTTP 200 OK in 12ms".to_string(),
+        };
+        assert_eq!(
+            title(Some(Status::Up), Some(&last)),
+            "up, checked 1970-01-02T00:00:00Z: HTTP 200 OK in 12ms"
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_responses_are_degraded() {
+        let addr = app().await;
+        let slow = |health: &str| {
+            format!(
+                "name = \"a\"\nurl = \"http://{addr}/\"\nhealth = {{ path = \"/slow\"{health} }}"
+            )
+        };
+        let degraded = check(&slow(", slow_ms = 100")).await.unwrap();
+        assert_eq!(degraded.status, Status::Degraded);
+        assert!(degraded.status.is_up());
+        assert!(
+            degraded.detail.starts_with("HTTP 200 OK in "),
+            "{}",
+            degraded.detail
+        );
+        // the default threshold is 1s
+        let up = check(&slow("")).await.unwrap();
+        assert_eq!(up.status, Status::Up);
+        assert_eq!(Status::css_class(Some(Status::Degraded)), "status-degraded");
+    }
+}
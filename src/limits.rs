@@ -0,0 +1,177 @@
+use std::{sync::LazyLock, time::Duration};
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioTimer;
+
+pub const ENV_VAR_MAX_BODY_BYTES: &str = "HOME_SERVICE_MAX_BODY_BYTES";
+pub const ENV_VAR_REQUEST_TIMEOUT: &str = "HOME_SERVICE_REQUEST_TIMEOUT_SECS";
+pub const ENV_VAR_CFG_READ_TIMEOUT: &str = "HOME_SERVICE_CFG_READ_TIMEOUT_SECS";
+pub const ENV_VAR_MAX_CFG_FILE_BYTES: &str = "HOME_SERVICE_MAX_CFG_FILE_BYTES";
+/// Plenty for a service definition
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_CFG_READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Far more than any service file needs while keeping a stray large file out of memory
+const DEFAULT_MAX_CFG_FILE_BYTES: u64 = 1024 * 1024;
+/// Long lived by design, `/poll` waits up to 25s and `/sse` never finishes.
+/// Proxied services are exempt too, see [`is_exempt`]
+const TIMEOUT_EXEMPT: &[&str] = &["/poll", "/sse"];
+
+static REQUEST_TIMEOUT: LazyLock<Duration> = LazyLock::new(request_timeout_from_env);
+
+/// The largest request body the api accepts
+pub fn max_body_bytes_from_env() -> usize {
+    let Ok(value) = std::env::var(ENV_VAR_MAX_BODY_BYTES) else {
+        return DEFAULT_MAX_BODY_BYTES;
+    };
+    match value.trim().parse::<usize>() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_MAX_BODY_BYTES} `{value}`: {e}");
+            DEFAULT_MAX_BODY_BYTES
+        }
+    }
+}
+
+/// The largest cfg file that is read, larger ones are skipped
+pub fn max_cfg_file_bytes_from_env() -> u64 {
+    let Ok(value) = std::env::var(ENV_VAR_MAX_CFG_FILE_BYTES) else {
+        return DEFAULT_MAX_CFG_FILE_BYTES;
+    };
+    match value.trim().parse::<u64>() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_MAX_CFG_FILE_BYTES} `{value}`: {e}");
+            DEFAULT_MAX_CFG_FILE_BYTES
+        }
+    }
+}
+
+/// How long a request has to produce a response before it's dropped with a 408,
+/// `/poll`, `/sse` and proxied services are exempt. Also how long a connection has to send its headers
+pub fn request_timeout_from_env() -> Duration {
+    secs_from_env(ENV_VAR_REQUEST_TIMEOUT, DEFAULT_REQUEST_TIMEOUT)
+}
+
+/// Middleware responding with a 408 when a handler takes longer than
+/// [`ENV_VAR_REQUEST_TIMEOUT`], except for the paths in [`is_exempt`]
+pub async fn timeout(req: Request, next: Next) -> Response {
+    if is_exempt(req.uri().path()) {
+        return next.run(req).await;
+    }
+    match tokio::time::timeout(*REQUEST_TIMEOUT, next.run(req)).await {
+        Ok(res) => res,
+        Err(_) => StatusCode::REQUEST_TIMEOUT.into_response(),
+    }
+}
+
+/// [`TIMEOUT_EXEMPT`] and proxied services, which can stream large downloads
+/// or long lived responses of their own
+fn is_exempt(path: &str) -> bool {
+    TIMEOUT_EXEMPT.contains(&path) || crate::proxy::is_proxied(path)
+}
+
+/// An http/1 connection builder that drops connections that never finish
+/// sending their headers, like a slow-loris client
+pub fn http1_builder() -> http1::Builder {
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .header_read_timeout(*REQUEST_TIMEOUT);
+    builder
+}
+
+/// How long reading every cfg file may take, so a hung network mount gives
+/// an error instead of a request that never finishes
+pub fn cfg_read_timeout_from_env() -> Duration {
+    secs_from_env(ENV_VAR_CFG_READ_TIMEOUT, DEFAULT_CFG_READ_TIMEOUT)
+}
+
+/// A whole number of seconds from `var`, `default` when it's unset, 0 or invalid
+fn secs_from_env(var: &str, default: Duration) -> Duration {
+    parse_secs(var, std::env::var(var).ok().as_deref(), default)
+}
+
+fn parse_secs(var: &str, value: Option<&str>, default: Duration) -> Duration {
+    let Some(value) = value else {
+        return default;
+    };
+    match value.trim().parse::<u64>() {
+        Ok(0) => {
+            tracing::warn!("{var} must be at least 1, using {default:?}");
+            default
+        }
+        Ok(secs) => Duration::from_secs(secs),
+        Err(e) => {
+            tracing::warn!("invalid {var} `{value}`: {e}");
+            default
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support;
+
+    /// Every route takes longer than the timeout to answer
+    async fn status(uri: &str) -> StatusCode {
+        let slow = || async {
+            tokio::time::sleep(*REQUEST_TIMEOUT + Duration::from_secs(1)).await;
+            "ok"
+        };
+        let app = Router::new()
+            .route("/", get(slow))
+            .route("/poll", get(slow))
+            .route("/sse", get(slow))
+            .layer(axum::middleware::from_fn(timeout));
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_requests_time_out_except_long_polling() {
+        assert_eq!(status("/").await, StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(status("/poll").await, StatusCode::OK);
+        assert_eq!(status("/sse").await, StatusCode::OK);
+    }
+
+    #[test]
+    fn secs_must_be_a_positive_number() {
+        let default = Duration::from_secs(5);
+        let parse = |value| parse_secs(ENV_VAR_CFG_READ_TIMEOUT, value, default);
+        assert_eq!(parse(None), default);
+        assert_eq!(parse(Some(" 12 ")), Duration::from_secs(12));
+        assert_eq!(parse(Some("0")), default);
+        assert_eq!(parse(Some("-1")), default);
+        assert_eq!(parse(Some("1.5")), default);
+    }
+
+    #[tokio::test]
+    async fn proxied_services_are_exempt() {
+        let _cfg = test_support::lock_cfg().await;
+        let path = test_support::cfg_dir().join("zz-proxied.toml");
+        std::fs::write(
+            &path,
+            "name = \"zz proxied\"\nurl = \"/zz-proxied\"\n\
+             proxy = { path = \"/zz-proxied\", upstream = \"http://proxied.local\" }\n",
+        )
+        .unwrap();
+        crate::read_cached_cfg().await.unwrap();
+        let proxied = is_exempt("/zz-proxied/files/large.iso");
+        std::fs::remove_file(&path).unwrap();
+        crate::read_cached_cfg().await.unwrap();
+        assert!(proxied);
+        assert!(is_exempt("/poll"));
+        assert!(!is_exempt("/api/services"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, PoisonError,
+    },
+    time::SystemTime,
+};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+pub const ENV_VAR_LOG_BUFFER: &str = "HOME_SERVICE_LOG_BUFFER";
+const DEFAULT_CAPACITY: usize = 500;
+
+static LINES: LazyLock<Mutex<VecDeque<String>>> = LazyLock::new(Default::default);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the latest `capacity` log lines for `/logs`
+pub struct LogBuffer {
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// `None` when the buffer has been disabled by setting its size to 0
+    pub fn from_env() -> Option<Self> {
+        let capacity = match std::env::var(ENV_VAR_LOG_BUFFER) {
+            Ok(value) => value.trim().parse().unwrap_or_else(|e| {
+                // tracing isn't set up yet
+                println!("invalid {ENV_VAR_LOG_BUFFER} `{value}`, using {DEFAULT_CAPACITY}: {e}");
+                DEFAULT_CAPACITY
+            }),
+            Err(_) => DEFAULT_CAPACITY,
+        };
+        ENABLED.store(capacity > 0, Ordering::Relaxed);
+        (capacity > 0).then_some(Self { capacity })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut line = format!(
+            "{} {:>5} {}:",
+            humantime::format_rfc3339_millis(SystemTime::now()),
+            meta.level(),
+            meta.target()
+        );
+        event.record(&mut LineVisitor(&mut line));
+        let mut lines = LINES.lock().unwrap_or_else(PoisonError::into_inner);
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// Appends the message and then every other field as `name=value`
+struct LineVisitor<'a>(&'a mut String);
+
+impl Visit for LineVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let _ = if field.name() == "message" {
+            write!(self.0, " {value:?}")
+        } else {
+            write!(self.0, " {}={value:?}", field.name())
+        };
+    }
+}
+
+/// The buffered log lines as plain text, oldest first. A 404 when the buffer
+/// is disabled or basic auth isn't, since the logs can include service urls
+/// and errors that shouldn't be public
+pub async fn render() -> Response {
+    if !ENABLED.load(Ordering::Relaxed) || !crate::auth::is_enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    text(&LINES.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+fn text(lines: &VecDeque<String>) -> Response {
+    let mut body = String::new();
+    for line in lines.iter() {
+        body.push_str(line);
+        body.push('\n');
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn keeps_the_latest_lines_in_order() {
+        let subscriber = tracing_subscriber::registry().with(LogBuffer { capacity: 3 });
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 1..=5 {
+                tracing::info!(target: "log_buffer_test", attempt = i, "event {i}");
+            }
+        });
+        let res = text(&LINES.lock().unwrap_or_else(PoisonError::into_inner));
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+        let body = test_support::text(res).await;
+        let lines: Vec<&str> = body
+            .lines()
+            .filter(|line| line.contains("log_buffer_test"))
+            .collect();
+        assert_eq!(lines.len(), 3, "{body}");
+        for (line, i) in lines.iter().zip(3..) {
+            assert!(
+                line.ends_with(&format!(" INFO log_buffer_test: event {i} attempt={i}")),
+                "{line}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_are_hidden_without_basic_auth() {
+        let res = test_support::get("/logs").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
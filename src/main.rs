@@ -1,197 +1,3752 @@
 use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
     convert::Infallible,
+    ffi::{OsStr, OsString},
     fmt::Display,
+    fs::Metadata,
+    future::Future,
+    hash::{Hash, Hasher},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock, OnceLock, PoisonError, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use axum::{
-    http::StatusCode,
+    extract::{ConnectInfo, DefaultBodyLimit, Query, RawQuery, Request},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
     response::{
         sse::{Event, KeepAlive, Sse},
-        Html,
+        Html, IntoResponse, Redirect, Response,
     },
     Router,
 };
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use clap::Parser;
+use dashboard::{DashboardConfig, GroupBy};
 use futures::{Stream, StreamExt};
-use serde::{Deserialize, Serialize};
-use tokio::fs::DirEntry;
-use tower_http::{services::ServeDir, trace::TraceLayer};
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use hyper::body::Incoming;
+use hyper_util::{rt::TokioIo, server::graceful::GracefulShutdown};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{io::AsyncReadExt, sync::watch};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tower::ServiceExt;
+use tower_http::{
+    compression::CompressionLayer, services::ServeDir, set_header::SetResponseHeader,
+    trace::TraceLayer,
+};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, EnvFilter, FmtSubscriber,
+};
+
+mod alert;
+mod api;
+mod auth;
+mod cors;
+mod dashboard;
+mod favicon;
+mod health;
+mod limits;
+mod log_buffer;
+mod markdown;
+mod metrics;
+mod proxy;
+mod rate_limit;
+mod request_id;
+mod security;
+mod targets;
+mod templates;
+#[cfg(test)]
+mod test_support;
+#[cfg(unix)]
+mod unix_socket;
+mod watcher;
 
-static ERROR_HTML_TEMPLATE: &str = include_str!("error.template.html");
-static INDEX_HTML_TEMPLATE: &str = include_str!("index.template.html");
+static NOT_FOUND_HTML_TEMPLATE: &str = include_str!("not-found.template.html");
+static FAVICON: &[u8] = include_bytes!("../assets/favicon.ico");
+const THEME_COOKIE: &str = "theme";
+const COLLAPSED_COOKIE: &str = "collapsed";
+const LAST_EVENT_ID: &str = "last-event-id";
 const ENV_VAR_CFG_DIR: &str = "HOME_SERVICE_CFG_DIR";
-static CFG_PATH: OnceLock<PathBuf> = OnceLock::new();
+const ENV_VAR_ASSETS_DIR: &str = "HOME_SERVICE_ASSETS_DIR";
+const ENV_VAR_PROFILE: &str = "HOME_SERVICE_PROFILE";
+const ENV_VAR_BIND: &str = "HOME_SERVICE_BIND";
+const ENV_VAR_UNIX_SOCKET: &str = "HOME_SERVICE_UNIX_SOCKET";
+const ENV_VAR_SSE_KEEPALIVE: &str = "HOME_SERVICE_SSE_KEEPALIVE_SECS";
+const ENV_VAR_LOG_FORMAT: &str = "HOME_SERVICE_LOG_FORMAT";
+const ENV_VAR_STRICT: &str = "HOME_SERVICE_STRICT";
+const ENV_VAR_FALLBACK: &str = "HOME_SERVICE_FALLBACK";
+const DEFAULT_BIND: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080));
+const MAX_CFG_DEPTH: usize = 16;
+/// How many cfg files are read at once
+const CFG_READ_CONCURRENCY: usize = 16;
+const DEFAULT_PER_PAGE: usize = 20;
+/// Every cfg dir in priority order, services in later dirs replace services
+/// with the same name in earlier ones
+static CFG_PATH: OnceLock<Vec<PathBuf>> = OnceLock::new();
+static CFG_CACHE: RwLock<Option<Arc<CachedCfg>>> = RwLock::new(None);
+/// When the cfg was last read from disk rather than the cache
+static LAST_RELOADED: RwLock<Option<SystemTime>> = RwLock::new(None);
+/// Set once the cfg dir has been read successfully, for `/ready`
+static CFG_READY: AtomicBool = AtomicBool::new(false);
+static CFG_READ_TIMEOUT: LazyLock<Duration> = LazyLock::new(limits::cfg_read_timeout_from_env);
+static MAX_CFG_FILE_BYTES: LazyLock<u64> = LazyLock::new(limits::max_cfg_file_bytes_from_env);
+static FALLBACK: LazyLock<Fallback> = LazyLock::new(fallback_from_env);
+static NEXT_EVENT_ID: AtomicU64 = AtomicU64::new(1);
+static SHUTDOWN: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// Short enough to get under most proxies' idle timeouts
+const POLL_TIMEOUT: Duration = Duration::from_secs(25);
 type ResponsePair = (StatusCode, Html<String>);
 
+/// A small dashboard of the services available on the local network
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Directories containing the service config files, separated like `PATH`.
+    /// Services in later directories replace those with the same name in earlier ones
+    #[arg(long, env = ENV_VAR_CFG_DIR, default_value = "./cfg")]
+    cfg_dir: OsString,
+    /// Load only the `base` and `<profile>` subdirectories of each cfg dir,
+    /// with services in `<profile>` replacing those in `base`
+    #[arg(long, env = ENV_VAR_PROFILE)]
+    profile: Option<String>,
+    /// Directory to serve `/assets` from
+    #[arg(long, env = ENV_VAR_ASSETS_DIR, default_value = "./assets")]
+    assets_dir: PathBuf,
+    /// Address to listen on, defaults to 0.0.0.0:8080
+    #[arg(long, env = ENV_VAR_BIND)]
+    bind: Option<String>,
+    /// Listen on a unix socket at this path instead of tcp
+    #[arg(long, env = ENV_VAR_UNIX_SOCKET)]
+    unix_socket: Option<PathBuf>,
+    /// Report any problems with the cfg files and exit instead of starting the server,
+    /// exits with 1 if there were any
+    #[arg(long)]
+    check: bool,
+}
+
 #[tokio::main]
 async fn main() {
-    let subscriber = FmtSubscriber::builder()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            println!("no filter in env!!");
-            EnvFilter::builder()
-                .parse("debug,home_services=trace")
-                .inspect_err(|e| {
-                    println!("Error parsing default filter: {e}");
-                })
-                .unwrap_or_default()
-        }))
-        // .with_max_level(Level::DEBUG)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-    let cfg_path = std::env::var(ENV_VAR_CFG_DIR)
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("./cfg"));
-    CFG_PATH.set(cfg_path).unwrap();
-    let static_files_service = ServeDir::new("assets").append_index_html_on_directories(false);
-    let app = Router::new()
+    let args = Args::parse();
+    init_tracing();
+    let cfg_dirs = parse_cfg_dirs(&args.cfg_dir);
+    CFG_PATH
+        .set(apply_profile(cfg_dirs, args.profile.as_deref()))
+        .unwrap();
+    if args.check {
+        std::process::exit(run_check().await);
+    }
+    auth::init_from_env();
+    templates::init_from_env();
+    if strict_from_env() {
+        if let Err(e) = check_cfg_strict().await {
+            tracing::error!("refusing to start in strict mode: {e}");
+            std::process::exit(1);
+        }
+    }
+    let _ = warm_cfg().await;
+    tokio::spawn(watcher::run(CFG_PATH.get().unwrap()));
+    if let Some(interval) = health::interval_from_env() {
+        health::restore().await;
+        tokio::spawn(health::run(interval));
+    }
+    if favicon::enabled_from_env() {
+        tokio::spawn(favicon::run());
+    }
+    match std::fs::canonicalize(&args.assets_dir) {
+        Ok(assets_dir) => tracing::info!("serving assets from `{}`", assets_dir.display()),
+        Err(e) => tracing::warn!(
+            "assets dir `{}` is unavailable: {e}",
+            args.assets_dir.display()
+        ),
+    }
+    let app = app(&args.assets_dir);
+    #[cfg(not(unix))]
+    if args.unix_socket.is_some() {
+        tracing::warn!("unix sockets aren't supported on this platform, listening on tcp");
+    }
+    let server = async {
+        #[cfg(unix)]
+        if let Some(path) = args.unix_socket.as_deref() {
+            if let Err(e) = unix_socket::serve(path, app).await {
+                tracing::error!("{e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        serve_tcp(parse_bind_addr(args.bind.as_deref()), app).await;
+    };
+    let grace_period = async {
+        shutdown_requested().await;
+        tokio::time::sleep(SHUTDOWN_GRACE_PERIOD).await;
+    };
+    tokio::select! {
+        _ = server => {},
+        _ = grace_period => {
+            tracing::warn!("connections still open after {SHUTDOWN_GRACE_PERIOD:?}, exiting anyway");
+        }
+    }
+    health::persist().await;
+    tracing::info!("shutdown complete");
+}
+
+/// Every route and the middleware shared by all of them
+fn app(assets_dir: &Path) -> Router {
+    // assets aren't fingerprinted so keep this short, `ServeDir` answers
+    // revalidation with a 304 using `Last-Modified`
+    let static_files_service = SetResponseHeader::if_not_present(
+        ServeDir::new(assets_dir).append_index_html_on_directories(false),
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=3600"),
+    );
+    let api = Router::new()
+        .route(
+            "/api/services",
+            axum::routing::get(api::list_services).post(api::create_service),
+        )
+        .route(
+            "/api/services/:name",
+            axum::routing::delete(api::delete_service),
+        )
+        // `create_service` reads its body as a `String`, which responds with a
+        // 413 past this instead of axum's default of 2MB
+        .layer(DefaultBodyLimit::max(limits::max_body_bytes_from_env()))
+        .layer(axum::middleware::from_fn(auth::require_basic_auth));
+    // cors goes outside of auth since preflight requests never have credentials
+    let api = match cors::layer_from_env() {
+        Some(cors) => api.layer(cors),
+        None => api,
+    };
+    // `get` routes also answer HEAD by running the GET handler and dropping the
+    // body, so monitors get the same status, `Content-Type` and `Content-Length`
+    Router::new()
         .route("/", axum::routing::get(index))
         .route("/index.html", axum::routing::get(index))
+        .route("/favicon.ico", axum::routing::get(favicon))
+        .route("/favicons/:key", axum::routing::get(favicon::serve))
+        .route("/search", axum::routing::get(search))
+        .route("/fragment/services", axum::routing::get(services_fragment))
+        .route("/theme", axum::routing::get(set_theme))
+        .route("/toggle-category", axum::routing::get(toggle_category))
+        .route("/reload", axum::routing::post(reload))
         .route("/sse", axum::routing::get(sse))
+        .route("/poll", axum::routing::get(poll))
+        .route("/metrics", axum::routing::get(metrics::render))
+        .route("/logs", axum::routing::get(log_buffer::render))
+        .route("/targets.json", axum::routing::get(targets::render))
         .nest_service("/assets", static_files_service)
-        .fallback(axum::routing::get(index))
-        .layer(TraceLayer::new_for_http());
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+        .fallback(proxy::fallback)
+        .layer(axum::middleware::from_fn(auth::require_basic_auth))
+        .merge(api)
+        // probes are added after the auth layer so they never require credentials
+        .route("/health", axum::routing::get(liveness))
+        .route("/ready", axum::routing::get(readiness))
+        .layer(axum::middleware::from_fn(security::headers))
+        .layer(axum::middleware::from_fn(rate_limit::limit))
+        .layer(axum::middleware::from_fn(limits::timeout))
+        .layer(axum::middleware::from_fn(metrics::track))
+        // the default predicate skips `text/event-stream` so sse is never buffered
+        .layer(CompressionLayer::new())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_id::make_span)
+                .on_response(request_id::on_response),
+        )
+        // outside of the trace layer so the span can include the id
+        .layer(axum::middleware::from_fn(request_id::propagate))
+}
+
+async fn serve_tcp(bind: SocketAddr, app: Router) {
+    let listener = tokio::net::TcpListener::bind(bind).await.unwrap();
     tracing::debug!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    serve_listener(listener, app, shutdown_signal()).await;
 }
 
-async fn index() -> Result<ResponsePair, ResponsePair> {
-    let cfg = read_cfg().await.map_err(|e| err(e, "reading cfg"))?;
-    Ok((
-        StatusCode::OK,
-        Html(INDEX_HTML_TEMPLATE.replace("{{services-list}}", &cfg.as_html())),
-    ))
+/// What [`serve_listener`] accepts connections from, so every transport
+/// shares one accept loop
+trait Listener {
+    type Io: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static;
+
+    /// The peer address is `None` for transports without one, like a unix
+    /// socket, and those requests aren't rate limited
+    fn accept(
+        &self,
+    ) -> impl Future<Output = std::io::Result<(Self::Io, Option<SocketAddr>)>> + Send;
 }
 
-async fn read_cfg() -> Result<Services, String> {
-    let path = CFG_PATH
-        .get()
-        .ok_or_else(|| "CFG_PATH is unset!".to_string())?;
-    let mut services = Services::default();
-    if !path.exists() {
-        tokio::fs::create_dir(path)
-            .await
-            .map_err(|e| format!("Error creating cfg dir: {e}"))?;
+impl Listener for tokio::net::TcpListener {
+    type Io = tokio::net::TcpStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Io, Option<SocketAddr>)> {
+        let (stream, addr) = tokio::net::TcpListener::accept(self).await?;
+        Ok((stream, Some(addr)))
+    }
+}
+
+/// Serve `app` on `listener` until `shutdown` resolves, then stop accepting
+/// and wait for the open connections to finish
+async fn serve_listener(listener: impl Listener, app: Router, shutdown: impl Future<Output = ()>) {
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, addr) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("failed to accept a connection: {e}");
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        // the peer address is needed to rate limit each client separately
+        let app = app.clone();
+        let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+            if let Some(addr) = addr {
+                req.extensions_mut().insert(ConnectInfo(addr));
+            }
+            app.clone().oneshot(req)
+        });
+        let conn =
+            graceful.watch(limits::http1_builder().serve_connection(TokioIo::new(stream), service));
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                tracing::debug!("connection closed with an error: {e}");
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+/// Resolves once ctrl-c or, on unix, SIGTERM is received and notifies anything
+/// waiting on [`shutdown_requested`]
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for ctrl-c: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown started");
+    SHUTDOWN.send_replace(true);
+}
+
+async fn shutdown_requested() {
+    let mut rx = SHUTDOWN.subscribe();
+    // the sender lives in a static so this can only fail if it was never set
+    let _ = rx.wait_for(|shutting_down| *shutting_down).await;
+}
+
+/// Install the global subscriber, logging json lines instead of the pretty
+/// output when `HOME_SERVICE_LOG_FORMAT=json`
+fn init_tracing() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        println!("no filter in env!!");
+        EnvFilter::builder()
+            .parse("debug,home_services=trace")
+            .inspect_err(|e| {
+                println!("Error parsing default filter: {e}");
+            })
+            .unwrap_or_default()
+    });
+    let json = log_format_is_json(std::env::var(ENV_VAR_LOG_FORMAT).ok().as_deref());
+    let subscriber = subscriber(
+        json,
+        filter,
+        BoxMakeWriter::new(std::io::stdout),
+        log_buffer::LogBuffer::from_env(),
+    );
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+}
+
+/// The pretty subscriber or, with `json`, one writing a json object with the
+/// span fields for every event
+fn subscriber(
+    json: bool,
+    filter: EnvFilter,
+    writer: BoxMakeWriter,
+    log_buffer: Option<log_buffer::LogBuffer>,
+) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    let builder = FmtSubscriber::builder()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    if json {
+        Box::new(
+            builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .finish()
+                .with(log_buffer),
+        )
     } else {
-        read_all_cfg_files(path, &mut services).await
+        Box::new(builder.finish().with(log_buffer))
     }
-    Ok(services)
 }
 
-async fn read_all_cfg_files(base_path: impl AsRef<Path>, services: &mut Services) {
-    let mut r = match tokio::fs::read_dir(base_path.as_ref()).await {
-        Ok(r) => r,
+fn log_format_is_json(value: Option<&str>) -> bool {
+    match value.map(str::trim) {
+        Some(v) if v.eq_ignore_ascii_case("json") => true,
+        None | Some("") => false,
+        Some(v) if v.eq_ignore_ascii_case("text") => false,
+        Some(v) => {
+            println!("invalid {ENV_VAR_LOG_FORMAT} `{v}`, expected `json` or `text`");
+            false
+        }
+    }
+}
+
+fn strict_from_env() -> bool {
+    flag_from_env(ENV_VAR_STRICT)
+}
+
+/// An on/off env var, off when it's unset or invalid
+fn flag_from_env(var: &str) -> bool {
+    parse_flag(var, std::env::var(var).ok().as_deref())
+}
+
+fn parse_flag(var: &str, value: Option<&str>) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => true,
+        "" | "0" | "false" | "no" => false,
+        _ => {
+            tracing::warn!("invalid {var} `{value}`, expected `1` or `0`");
+            false
+        }
+    }
+}
+
+/// Read the cfg, failing if any file couldn't be parsed or had an invalid
+/// service instead of skipping it
+async fn check_cfg_strict() -> Result<(), String> {
+    require_no_errors(&read_cfg().await?)
+}
+
+fn require_no_errors(cfg: &Services) -> Result<(), String> {
+    if cfg.errors.is_empty() {
+        return Ok(());
+    }
+    for (path, e) in &cfg.errors {
+        tracing::error!("invalid cfg file `{}`: {e}", path.display());
+    }
+    Err(format!("{} cfg error(s)", cfg.errors.len()))
+}
+
+/// Read the cfg once before serving so the first request doesn't have to, a
+/// failure here is only logged since the error page explains it to visitors
+async fn warm_cfg() -> Result<Services, String> {
+    read_cfg()
+        .await
+        .inspect(|cfg| {
+            tracing::info!(
+                "loaded {} service(s), {} cfg error(s)",
+                cfg.services.len(),
+                cfg.errors.len()
+            )
+        })
+        .inspect_err(|e| tracing::error!("failed to read cfg at startup: {e}"))
+}
+
+/// Print every problem found by [`cfg_problems`], returning the exit code
+async fn run_check() -> i32 {
+    match cfg_problems().await {
+        Ok(problems) if problems.is_empty() => {
+            println!("no problems found");
+            0
+        }
+        Ok(problems) => {
+            for problem in &problems {
+                println!("{problem}");
+            }
+            println!("{} problem(s) found", problems.len());
+            1
+        }
         Err(e) => {
-            tracing::warn!(
-                "read dir failed for `{}`: {e}",
-                base_path.as_ref().display()
-            );
-            return;
+            println!("failed to read cfg: {e}");
+            1
         }
+    }
+}
+
+/// Files that couldn't be parsed, services with invalid urls and services
+/// sharing a name, one line each
+async fn cfg_problems() -> Result<Vec<String>, String> {
+    Ok(problems(&read_cfg().await?))
+}
+
+fn problems(cfg: &Services) -> Vec<String> {
+    cfg.errors
+        .iter()
+        .map(|(path, e)| format!("{}: {e}", path.display()))
+        .collect()
+}
+
+fn parse_cfg_dirs(value: &OsStr) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = std::env::split_paths(value)
+        .filter(|p| !p.as_os_str().is_empty())
+        .collect();
+    if dirs.is_empty() {
+        tracing::warn!("no cfg dirs provided, using `./cfg`");
+        return vec![PathBuf::from("./cfg")];
+    }
+    dirs
+}
+
+/// Replace every cfg dir with its `base` and `<profile>` subdirectories, in
+/// that order so the profile wins. Profiles that aren't a single path
+/// component are ignored so they can't point outside of the cfg dirs
+fn apply_profile(dirs: Vec<PathBuf>, profile: Option<&str>) -> Vec<PathBuf> {
+    let Some(profile) = profile.map(str::trim).filter(|p| !p.is_empty()) else {
+        return dirs;
     };
-    loop {
-        let entry: tokio::fs::DirEntry = match r.next_entry().await {
-            Ok(Some(entry)) => entry,
-            Ok(None) => break,
-            Err(e) => {
-                tracing::error!("Error from read-dir: {e}");
-                continue;
+    let is_component = matches!(
+        Path::new(profile).components().collect::<Vec<_>>()[..],
+        [std::path::Component::Normal(_)]
+    );
+    if !is_component {
+        tracing::warn!("invalid {ENV_VAR_PROFILE} `{profile}`, loading the cfg dirs as is");
+        return dirs;
+    }
+    tracing::info!("using the `{profile}` cfg profile");
+    dirs.into_iter()
+        .flat_map(|dir| {
+            let ignored = dir.join(dashboard::DASHBOARD_FILE);
+            if ignored.exists() {
+                tracing::warn!(
+                    "ignoring `{}`, with a profile it belongs in `base` or `{profile}`",
+                    ignored.display()
+                );
             }
+            let base = dir.join("base");
+            let profile = dir.join(profile);
+            if base == profile {
+                vec![base]
+            } else {
+                vec![base, profile]
+            }
+        })
+        .collect()
+}
+
+fn parse_bind_addr(value: Option<&str>) -> SocketAddr {
+    let Some(value) = value else {
+        return DEFAULT_BIND;
+    };
+    value.trim().parse().unwrap_or_else(|e| {
+        tracing::warn!("invalid bind address `{value}`, using {DEFAULT_BIND}: {e}");
+        DEFAULT_BIND
+    })
+}
+
+async fn index(
+    jar: CookieJar,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ResponsePair> {
+    let page = ListQuery::parse(query.as_deref(), &headers);
+    let cached = read_cached_cfg().await.map_err(|e| err(e, "reading cfg"))?;
+    let services_html = list_html(&cached, &page);
+    let html = render_index(&cached.services, Theme::from_cookies(&jar), &services_html);
+    let etag = etag_for(&html);
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        // always revalidate, the page changes with the cfg and health checks
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+        (header::VARY, "Cookie".to_string()),
+    ];
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    Ok((StatusCode::OK, cache_headers, Html(html)).into_response())
+}
+
+/// A weak validator since the compression layer may change the bytes sent
+fn etag_for(body: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!(r#"W/"{:016x}""#, hasher.finish())
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    value
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// The services list for `page`, the cached render when it's the default view
+fn list_html(cached: &CachedCfg, page: &ListQuery) -> Arc<str> {
+    if page.is_default() {
+        return cached.html();
+    }
+    let mut services = cached.services.clone();
+    favicon::apply(&mut services);
+    Arc::from(services.page_html(page))
+}
+
+fn render_index(cfg: &Services, theme: Theme, services_html: &str) -> String {
+    fill_index(templates::index(), cfg, theme, services_html)
+}
+
+fn fill_index(template: &str, cfg: &Services, theme: Theme, services_html: &str) -> String {
+    template
+        .replace("{{theme}}", theme.as_str())
+        .replace("{{other-theme}}", theme.other().as_str())
+        .replace("{{other-theme-label}}", theme.other().label())
+        .replace("{{title}}", &cfg.dashboard.title_html())
+        .replace("{{logo}}", &cfg.dashboard.logo_html())
+        .replace("{{dashboard-style}}", &cfg.dashboard.style_html())
+        .replace("{{cfg-errors}}", &cfg.errors_html())
+        .replace("{{status-banner}}", &health::summary_html(&cfg.services))
+        .replace("{{status-legend}}", &health::legend_html())
+        .replace("{{services-list}}", services_html)
+        .replace("{{last-reloaded}}", &last_reloaded_html())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn from_cookies(jar: &CookieJar) -> Self {
+        match jar.get(THEME_COOKIE).map(Cookie::value) {
+            Some("dark") => Self::Dark,
+            _ => Self::Light,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Light => "Light mode",
+            Self::Dark => "Dark mode",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Light => Self::Dark,
+            Self::Dark => Self::Light,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ThemeQuery {
+    mode: Theme,
+}
+
+/// Persist the chosen theme in a cookie and send the user back to where they came from
+async fn set_theme(
+    jar: CookieJar,
+    headers: HeaderMap,
+    Query(query): Query<ThemeQuery>,
+) -> (CookieJar, Redirect) {
+    let cookie = Cookie::build((THEME_COOKIE, query.mode.as_str()))
+        .path("/")
+        .same_site(SameSite::Lax)
+        .permanent()
+        .build();
+    (jar.add(cookie), Redirect::to(&same_site_referer(&headers)))
+}
+
+/// `?name=` toggles that category while `?pinned=true` toggles the pinned
+/// section, which is kept apart from any category that is also named "Pinned"
+#[derive(Debug, Deserialize)]
+struct ToggleCategoryQuery {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pinned: bool,
+    /// Flips the current state when not given
+    open: Option<bool>,
+}
+
+/// The sections that are rendered closed, from the cookie set by `/toggle-category`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Collapsed {
+    categories: HashSet<String>,
+    pinned: bool,
+}
+
+impl Collapsed {
+    /// Stored url encoded as repeated `c` params and a `p` param for the
+    /// pinned section. The value is read and written as is rather than
+    /// through [`CookieJar`], which would percent encode it a second time and
+    /// leave `/assets/index.js` unable to parse it with `URLSearchParams`
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut ret = Self::default();
+        let cookie = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(Cookie::split_parse)
+            .filter_map(Result::ok)
+            .find(|cookie| cookie.name() == COLLAPSED_COOKIE);
+        let Some(cookie) = cookie else {
+            return ret;
         };
-        let Some(service) = read_single_cfg(entry).await else {
-            continue;
-        };
-        services.services.push(service);
+        for (key, value) in url::form_urlencoded::parse(cookie.value().as_bytes()) {
+            match key.as_ref() {
+                "c" => {
+                    ret.categories.insert(value.into_owned());
+                }
+                "p" => ret.pinned = true,
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    fn is_empty(&self) -> bool {
+        self.categories.is_empty() && !self.pinned
+    }
+
+    fn cookie_value(&self) -> String {
+        let mut sorted: Vec<&String> = self.categories.iter().collect();
+        sorted.sort();
+        let mut value = url::form_urlencoded::Serializer::new(String::new());
+        if self.pinned {
+            value.append_pair("p", "1");
+        }
+        value
+            .extend_pairs(sorted.into_iter().map(|category| ("c", category)))
+            .finish()
+    }
+}
+
+/// Persist whether a category is collapsed and send the user back to where they came from
+async fn toggle_category(
+    headers: HeaderMap,
+    Query(query): Query<ToggleCategoryQuery>,
+) -> ([(header::HeaderName, String); 1], Redirect) {
+    let mut collapsed = Collapsed::from_headers(&headers);
+    if query.pinned {
+        collapsed.pinned = !query.open.unwrap_or(collapsed.pinned);
+    } else {
+        let open = query
+            .open
+            .unwrap_or_else(|| collapsed.categories.contains(&query.name));
+        if open {
+            collapsed.categories.remove(&query.name);
+        } else {
+            collapsed.categories.insert(query.name);
+        }
     }
+    let cookie = Cookie::build((COLLAPSED_COOKIE, collapsed.cookie_value()))
+        .path("/")
+        .same_site(SameSite::Lax)
+        .permanent()
+        .build();
+    // `Display` leaves the value unencoded
+    (
+        [(header::SET_COOKIE, cookie.to_string())],
+        Redirect::to(&same_site_referer(&headers)),
+    )
 }
 
-async fn read_single_cfg(entry: DirEntry) -> Option<Service> {
-    let s = tokio::fs::read_to_string(entry.path())
+/// Re-read the cfg, ignoring the cache, and send the user back to where they came from.
+/// Connected sse clients get the fresh list like they do for a change on disk
+async fn reload(headers: HeaderMap) -> Result<Redirect, ResponsePair> {
+    tracing::debug!("POST: /reload");
+    watcher::reload(Vec::new())
         .await
-        .inspect_err(|e| tracing::warn!("Error reading `{}`:{e}", entry.path().display()))
-        .ok()?;
-    toml::from_str(&s)
-        .inspect_err(|e| {
-            tracing::warn!("Failed to serialize `{}`: {e}", entry.path().display());
-            tracing::debug!("bad toml:\n`{s}`");
+        .map_err(|e| err(e, "reloading cfg"))?;
+    Ok(Redirect::to(&same_site_referer(&headers)))
+}
+
+/// The path and query of the referer, so redirecting to it can never leave this site
+fn same_site_referer(headers: &HeaderMap) -> String {
+    headers
+        .get(header::REFERER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| url::Url::parse(v).ok())
+        .map(|url| match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
         })
-        .ok()
+        .unwrap_or_else(|| "/".to_string())
 }
 
-fn err(e: impl Display, context: impl Display) -> ResponsePair {
-    tracing::warn!("Generating error html:\n{e}\n{context}");
+async fn favicon() -> impl IntoResponse {
     (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Html(
-            ERROR_HTML_TEMPLATE
-                .replace("{{context}", &format!("{context}"))
-                .replace("{{e}}", &format!("{e}")),
-        ),
+        [
+            (header::CONTENT_TYPE, "image/x-icon"),
+            (header::CACHE_CONTROL, "public, max-age=604800"),
+        ],
+        FAVICON,
     )
 }
 
-async fn sse() -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ResponsePair> {
-    tracing::debug!("GET: /sse");
-    let watcher = inotify::Inotify::init().map_err(|e| {
-        tracing::warn!("Error initing inotify: {e}");
-        err(e, "setting up inotify")
-    })?;
-    watcher
-        .watches()
-        .add(
-            CFG_PATH.get().unwrap(),
-            inotify::WatchMask::CREATE | inotify::WatchMask::MODIFY,
-        )
-        .map_err(|e| {
+async fn liveness() -> &'static str {
+    "ok"
+}
+
+async fn readiness() -> (StatusCode, &'static str) {
+    readiness_for(CFG_READY.load(Ordering::Relaxed))
+}
+
+fn readiness_for(cfg_ready: bool) -> (StatusCode, &'static str) {
+    if cfg_ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "cfg not read yet")
+    }
+}
+
+/// What to respond with for a path that isn't a route or proxied
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Fallback {
+    NotFound,
+    /// Serve the dashboard, for clients that do their own routing
+    Index,
+    /// Redirect to this path on this site
+    Redirect(String),
+}
+
+fn fallback_from_env() -> Fallback {
+    let Ok(value) = std::env::var(ENV_VAR_FALLBACK) else {
+        return Fallback::NotFound;
+    };
+    match parse_fallback(value.trim()) {
+        Some(fallback) => fallback,
+        None => {
             tracing::warn!(
-                "error setting up inotify for cfg path `{}`: {e}",
-                CFG_PATH.get().unwrap().display()
+                "invalid {ENV_VAR_FALLBACK} `{value}`, expected index, notfound or redirect:/path"
             );
-            err(e, "setting up inotify for cfg path")
-        })?;
-    let buf = [0u8; 65_535];
-    let stream = watcher
-        .into_event_stream(buf)
-        .map_err(|e| err(e, "watcher into event stream"))?;
-    tracing::debug!("Completing sse handshake");
-    Ok(Sse::new(stream.map(|_| {
-        tracing::debug!("Sending update event");
-        Ok(Event::default().data("update"))
-    }))
-    .keep_alive(KeepAlive::default()))
-}
-
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct Services {
-    #[serde(rename = "service")]
-    services: Vec<Service>,
+            Fallback::NotFound
+        }
+    }
 }
 
-impl Services {
-    fn as_html(&self) -> String {
-        self.services
+fn parse_fallback(value: &str) -> Option<Fallback> {
+    match value {
+        "notfound" => Some(Fallback::NotFound),
+        "index" => Some(Fallback::Index),
+        _ => {
+            let path = value.strip_prefix("redirect:")?;
+            // `//host` would leave this site
+            (path.starts_with('/') && !path.starts_with("//"))
+                .then(|| Fallback::Redirect(path.to_string()))
+        }
+    }
+}
+
+/// Respond to a request nothing else handled according to [`ENV_VAR_FALLBACK`],
+/// anything but a GET or HEAD is always a 404
+async fn unmatched(req: Request) -> Response {
+    respond_unmatched(&FALLBACK, req).await
+}
+
+async fn respond_unmatched(fallback: &Fallback, req: Request) -> Response {
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    match fallback {
+        Fallback::Index if is_read => {
+            let jar = CookieJar::from_headers(req.headers());
+            let query = req.uri().query().map(String::from);
+            index(jar, req.headers().clone(), RawQuery(query))
+                .await
+                .into_response()
+        }
+        Fallback::Redirect(path) if is_read => {
+            tracing::debug!("no route for `{}`, redirecting to `{path}`", req.uri());
+            Redirect::to(path).into_response()
+        }
+        _ => not_found(req.uri().clone()).await.into_response(),
+    }
+}
+
+async fn not_found(uri: Uri) -> ResponsePair {
+    tracing::debug!("no route for `{uri}`");
+    (
+        StatusCode::NOT_FOUND,
+        Html(NOT_FOUND_HTML_TEMPLATE.replace("{{path}}", &escape_html(uri.path()))),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+/// `?page=` and `?per_page=` for showing a slice of the services list and
+/// any number of `?tag=` to only show services with every one of those tags.
+/// Everything is shown when none are given.
+#[derive(Debug, Default)]
+struct ListQuery {
+    /// 1 based, defaults to the first page
+    page: Option<usize>,
+    per_page: Option<usize>,
+    tags: Vec<String>,
+    collapsed: Collapsed,
+}
+
+impl ListQuery {
+    /// Parsed by hand since `tag` can repeat, invalid numbers are ignored
+    fn parse(query: Option<&str>, headers: &HeaderMap) -> Self {
+        let mut ret = Self {
+            collapsed: Collapsed::from_headers(headers),
+            ..Self::default()
+        };
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match key.as_ref() {
+                "page" => ret.page = value.trim().parse().ok(),
+                "per_page" => ret.per_page = value.trim().parse().ok(),
+                "tag" if !value.trim().is_empty() => ret.tags.push(value.trim().to_string()),
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    /// Every service with every category open, the view that is cached
+    fn is_default(&self) -> bool {
+        !self.is_paginated() && self.tags.is_empty() && self.collapsed.is_empty()
+    }
+
+    fn is_paginated(&self) -> bool {
+        self.page.is_some() || self.per_page.is_some()
+    }
+
+    /// The tags as query params to keep the filter when changing pages
+    fn tag_params(&self) -> String {
+        self.tags
             .iter()
-            .map(Service::as_html)
-            .map(|s| format!("<li>{s}</li>"))
+            .map(|tag| {
+                format!(
+                    "&tag={}",
+                    url::form_urlencoded::byte_serialize(tag.as_bytes()).collect::<String>()
+                )
+            })
+            .collect()
+    }
+
+    /// Which tags are being filtered by and a link back to everything, empty
+    /// when there is no filter
+    fn filter_html(&self) -> String {
+        if self.tags.is_empty() {
+            return String::new();
+        }
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| format!("<code>{}</code>", escape_html(tag)))
             .collect::<Vec<_>>()
-            .join("")
+            .join(", ");
+        format!(
+            r#"<li class="tag-filter">Showing services tagged {tags} <a href="/">clear filter</a></li>"#
+        )
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Service {
-    name: String,
-    url: String,
-    desc: String,
+/// Just the services list items, for polling with something like htmx
+async fn services_fragment(
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse, ResponsePair> {
+    let page = ListQuery::parse(query.as_deref(), &headers);
+    let cached = read_cached_cfg().await.map_err(|e| err(e, "reading cfg"))?;
+    Ok((
+        [(header::CACHE_CONTROL, "no-store")],
+        Html(list_html(&cached, &page).to_string()),
+    ))
 }
 
-impl Service {
-    fn as_html(&self) -> String {
-        let Self { name, url, desc } = self;
+async fn search(Query(query): Query<SearchQuery>) -> Result<ResponsePair, ResponsePair> {
+    let cfg = read_cfg().await.map_err(|e| err(e, "reading cfg"))?;
+    let found = cfg.matching(query.q.trim());
+    if found.services.is_empty() {
+        return Ok((
+            StatusCode::OK,
+            Html(format!(
+                r#"<li class="empty-state">No services match `{}`</li>"#,
+                escape_html(query.q.trim())
+            )),
+        ));
+    }
+    Ok((StatusCode::OK, Html(found.as_html())))
+}
+
+async fn read_cfg() -> Result<Services, String> {
+    let cached = read_cached_cfg().await?;
+    let mut services = cached.services.clone();
+    favicon::apply(&mut services);
+    Ok(services)
+}
+
+/// [`Services::as_html`] of the cfg, only rendered again once the cfg, a
+/// health check or a favicon has changed
+async fn read_cfg_html() -> Result<Arc<str>, String> {
+    Ok(read_cached_cfg().await?.html())
+}
+
+/// `read`'s result, or an error once it has taken longer than `timeout`
+async fn read_within<T>(
+    timeout: Duration,
+    read: impl Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    tokio::time::timeout(timeout, read).await.map_err(|_| {
         format!(
-            r#"<article class="service-entry" onclick="goto('{url}')"><h2>{name}</h2><span>{desc}</span></article>"#
+            "Timed out after {timeout:?} reading the cfg dirs, they may be on an unresponsive mount"
         )
+    })?
+}
+
+/// The cfg without favicons applied, shared with the cache instead of cloned
+async fn read_cached_cfg() -> Result<Arc<CachedCfg>, String> {
+    // nothing is cached until the read completes so giving up part way is safe
+    let cached = read_within(*CFG_READ_TIMEOUT, load_cfg()).await?;
+    CFG_READY.store(true, Ordering::Relaxed);
+    Ok(cached)
+}
+
+async fn load_cfg() -> Result<Arc<CachedCfg>, String> {
+    let start = Instant::now();
+    let dirs = CFG_PATH
+        .get()
+        .ok_or_else(|| "CFG_PATH is unset!".to_string())?;
+    let listings = list_cfg_dirs(dirs).await?;
+    if let Some(cached) = cached_cfg(&listings) {
+        tracing::trace!("cfg unchanged, using cache");
+        return Ok(cached);
+    }
+    let services = read_listings(&listings).await;
+    let cached = Arc::new(CachedCfg {
+        services,
+        newest: listings.iter().filter_map(|l| l.newest).max(),
+        file_count: listings.iter().map(|l| l.files.len()).sum(),
+        html: RwLock::new(None),
+    });
+    // only reads that re-parse the files, cache hits would drown them out
+    metrics::record_cfg_read(start.elapsed());
+    metrics::set_configured(
+        cached
+            .services
+            .services
+            .iter()
+            .filter(|s| s.enabled)
+            .count(),
+    );
+    *CFG_CACHE.write().unwrap_or_else(PoisonError::into_inner) = Some(cached.clone());
+    *LAST_RELOADED
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(SystemTime::now());
+    Ok(cached)
+}
+
+/// List every cfg dir, creating any that don't exist yet
+async fn list_cfg_dirs(dirs: &[PathBuf]) -> Result<Vec<CfgListing>, String> {
+    let mut listings = Vec::with_capacity(dirs.len());
+    let mut visited = HashSet::new();
+    for dir in dirs {
+        if !dir.exists() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Error creating cfg dir `{}`: {e}", dir.display()))?;
+        }
+        listings.push(list_cfg_files(dir, &mut visited).await);
+    }
+    Ok(listings)
+}
+
+/// Read the dashboard cfg and services of every listing, services in later
+/// listings replace those with the same name in earlier ones
+async fn read_listings(listings: &[CfgListing]) -> Services {
+    let mut services = Services::default();
+    if let Some(dashboard) = listings.iter().rev().find_map(|l| l.dashboard.as_ref()) {
+        match DashboardConfig::read(dashboard).await {
+            Ok(cfg) => services.dashboard = cfg,
+            Err(e) => {
+                tracing::warn!("Failed to read `{}`: {e}", dashboard.display());
+                services.errors.push((dashboard.clone(), e));
+            }
+        }
+    }
+    for listing in listings {
+        let mut dir_services = Services::default();
+        read_all_cfg_files(&listing.files, &mut dir_services).await;
+        services.errors.append(&mut dir_services.errors);
+        let names: HashSet<&str> = dir_services
+            .services
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        services.services.retain(|existing| {
+            let replaced = names.contains(existing.name.as_str());
+            if replaced {
+                tracing::debug!("`{}` is overridden by a later cfg dir", existing.name);
+            }
+            !replaced
+        });
+        services.services.append(&mut dir_services.services);
+    }
+    services
+}
+
+struct CachedCfg {
+    services: Services,
+    newest: Option<SystemTime>,
+    file_count: usize,
+    /// [`Services::as_html`] with favicons applied, rendered on first use
+    html: RwLock<Option<Arc<str>>>,
+}
+
+impl CachedCfg {
+    /// Nothing was added, removed or modified since this was read
+    fn is_fresh(&self, listings: &[CfgListing]) -> bool {
+        let file_count: usize = listings.iter().map(|l| l.files.len()).sum();
+        let newest = listings.iter().filter_map(|l| l.newest).max();
+        self.file_count == file_count && newest <= self.newest
+    }
+
+    fn html(&self) -> Arc<str> {
+        if let Some(html) = &*self.html.read().unwrap_or_else(PoisonError::into_inner) {
+            return html.clone();
+        }
+        let mut services = self.services.clone();
+        favicon::apply(&mut services);
+        let html: Arc<str> = Arc::from(services.as_html());
+        *self.html.write().unwrap_or_else(PoisonError::into_inner) = Some(html.clone());
+        html
+    }
+}
+
+fn cached_cfg(listings: &[CfgListing]) -> Option<Arc<CachedCfg>> {
+    let cache = CFG_CACHE.read().unwrap_or_else(PoisonError::into_inner);
+    cache
+        .as_ref()
+        .filter(|cached| cached.is_fresh(listings))
+        .cloned()
+}
+
+/// Run `f` with the cached cfg, `None` if nothing has been cached yet
+fn cached_services<T>(f: impl FnOnce(&Services) -> T) -> Option<T> {
+    let cache = CFG_CACHE.read().unwrap_or_else(PoisonError::into_inner);
+    cache.as_ref().map(|cached| f(&cached.services))
+}
+
+fn last_reloaded() -> Option<String> {
+    let reloaded = *LAST_RELOADED.read().unwrap_or_else(PoisonError::into_inner);
+    reloaded.map(|at| humantime::format_rfc3339_seconds(at).to_string())
+}
+
+/// The footer `<time>` that is kept current by the `reloaded` sse event
+fn last_reloaded_html() -> String {
+    match last_reloaded() {
+        Some(at) => format!(r#"<time id="last-reloaded" datetime="{at}">{at}</time>"#),
+        None => r#"<time id="last-reloaded">never</time>"#.to_string(),
+    }
+}
+
+fn invalidate_cfg_cache() {
+    *CFG_CACHE.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Render the services list again on next use, for changes that aren't in
+/// the cfg files like health checks and favicons
+fn invalidate_cfg_html() {
+    if let Some(cached) = &*CFG_CACHE.read().unwrap_or_else(PoisonError::into_inner) {
+        *cached.html.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Every file found in a cfg dir along with the newest modification
+/// time of any of those files or the directories containing them.
+struct CfgListing {
+    files: Vec<PathBuf>,
+    dashboard: Option<PathBuf>,
+    newest: Option<SystemTime>,
+}
+
+/// `visited` is shared between cfg dirs so a dir that is reachable from more
+/// than one of them is only read once
+async fn list_cfg_files(base_path: impl AsRef<Path>, visited: &mut HashSet<PathBuf>) -> CfgListing {
+    let mut listing = CfgListing {
+        files: Vec::new(),
+        dashboard: None,
+        newest: None,
+    };
+    let mut pending = vec![(base_path.as_ref().to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        match tokio::fs::canonicalize(&dir).await {
+            Ok(canonical) => {
+                if !visited.insert(canonical) {
+                    tracing::debug!("skipping already visited dir `{}`", dir.display());
+                    continue;
+                }
+            }
+            Err(e) => {
+                tracing::warn!("failed to canonicalize `{}`: {e}", dir.display());
+                continue;
+            }
+        }
+        // a directory's mtime changes when entries are added, removed or renamed
+        if let Ok(md) = tokio::fs::metadata(&dir).await {
+            listing.observe_mtime(&md);
+        }
+        let mut r = match tokio::fs::read_dir(&dir).await {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("read dir failed for `{}`: {e}", dir.display());
+                continue;
+            }
+        };
+        loop {
+            let entry: tokio::fs::DirEntry = match r.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Error from read-dir: {e}");
+                    continue;
+                }
+            };
+            // `metadata` follows symlinks so linked directories are scanned too
+            let md = match tokio::fs::metadata(entry.path()).await {
+                Ok(md) => md,
+                Err(e) => {
+                    tracing::warn!("failed to stat `{}`: {e}", entry.path().display());
+                    continue;
+                }
+            };
+            if md.is_dir() {
+                if depth >= MAX_CFG_DEPTH {
+                    tracing::warn!(
+                        "skipping `{}`, max cfg dir depth of {MAX_CFG_DEPTH} reached",
+                        entry.path().display()
+                    );
+                } else {
+                    pending.push((entry.path(), depth + 1));
+                }
+                continue;
+            }
+            listing.observe_mtime(&md);
+            if depth == 0 && entry.file_name() == dashboard::DASHBOARD_FILE {
+                listing.dashboard = Some(entry.path());
+                continue;
+            }
+            // stylesheets for `custom_css_path` live alongside the services
+            if entry.path().extension().is_some_and(|ext| ext == "css") {
+                continue;
+            }
+            listing.files.push(entry.path());
+        }
+    }
+    // read_dir's order is platform dependent, sorting keeps which of two
+    // services sharing a name wins the same everywhere
+    listing.files.sort();
+    listing
+}
+
+impl CfgListing {
+    fn observe_mtime(&mut self, md: &Metadata) {
+        let Ok(modified) = md.modified() else {
+            return;
+        };
+        if self.newest.map(|newest| modified > newest).unwrap_or(true) {
+            self.newest = Some(modified);
+        }
+    }
+}
+
+/// Files are read [`CFG_READ_CONCURRENCY`] at a time but handled in order, so
+/// only that many are ever buffered and the first file to define a name is
+/// always the same one
+async fn read_all_cfg_files(files: &[PathBuf], services: &mut Services) {
+    let mut indices: HashMap<String, usize> = services
+        .services
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.name.clone(), i))
+        .collect();
+    // owned paths keep the future `Send` for the handlers awaiting it
+    let mut reads = std::pin::pin!(futures::stream::iter(files.to_vec())
+        .map(|path| async move {
+            let found = read_single_cfg(&path).await;
+            (path, found)
+        })
+        .buffered(CFG_READ_CONCURRENCY));
+    while let Some((path, found)) = reads.next().await {
+        let found = match found {
+            Ok(found) => found,
+            Err(e) => {
+                services.errors.push((path.clone(), e));
+                continue;
+            }
+        };
+        for mut service in found {
+            service.alt_urls.retain(|url| match validate_url(url) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("ignoring an alt url of `{}`: {e}", service.name);
+                    services
+                        .errors
+                        .push((path.clone(), format!("`{}` alt url {e}", service.name)));
+                    false
+                }
+            });
+            if let Some(color) = service
+                .color
+                .take_if(|color| !dashboard::is_safe_css_color(color))
+            {
+                tracing::warn!("ignoring invalid color `{color}` of `{}`", service.name);
+            }
+            if let Err(e) = validate_url(&service.url) {
+                tracing::warn!("skipping `{}` from `{}`: {e}", service.name, path.display());
+                services
+                    .errors
+                    .push((path.clone(), format!("`{}` {e}", service.name)));
+                continue;
+            }
+            // the first file to define a name wins
+            if let Some(&existing) = indices.get(&service.name) {
+                let existing = services.services[existing]
+                    .source
+                    .as_deref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                tracing::warn!(
+                    "skipping `{}` from `{}`, it is already defined in `{existing}`",
+                    service.name,
+                    path.display()
+                );
+                services.errors.push((
+                    path.clone(),
+                    format!("`{}` is already defined in `{existing}`", service.name),
+                ));
+                continue;
+            }
+            indices.insert(service.name.clone(), services.services.len());
+            services.services.push(service);
+        }
+    }
+}
+
+async fn read_single_cfg(path: &Path) -> Result<Vec<Service>, String> {
+    let s = read_cfg_text(path).await.inspect_err(|e| {
+        tracing::warn!("Error reading `{}`: {e}", path.display());
+    })?;
+    let (_, doc) = CfgFormat::parse_file(path, &s).inspect_err(|e| {
+        tracing::warn!("Failed to serialize `{}`: {e}", path.display());
+        tracing::debug!("bad cfg:\n`{s}`");
+    })?;
+    let modified = tokio::fs::metadata(path)
+        .await
+        .and_then(|md| md.modified())
+        .ok();
+    let mut services = doc.into_services();
+    for service in services.iter_mut() {
+        service.expand_env();
+        service.source = Some(path.to_path_buf());
+        service.modified = modified;
+    }
+    Ok(services)
+}
+
+/// Read at most [`MAX_CFG_FILE_BYTES`] of `path`, a file that is larger or
+/// isn't utf-8 is an error without the rest of it ever being read
+async fn read_cfg_text(path: &Path) -> Result<String, String> {
+    read_capped_text(path, *MAX_CFG_FILE_BYTES).await
+}
+
+/// [`read_cfg_text`] with a limit other than [`MAX_CFG_FILE_BYTES`]
+async fn read_capped_text(path: &Path, max: u64) -> Result<String, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Error reading file: {e}"))?;
+    let mut bytes = Vec::new();
+    // one byte past the limit is enough to know the file is too large
+    file.take(max.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|e| format!("Error reading file: {e}"))?;
+    if bytes.len() as u64 > max {
+        return Err(format!(
+            "File is larger than the {max} byte limit, skipping it"
+        ));
+    }
+    String::from_utf8(bytes).map_err(|e| format!("File isn't valid utf-8: {e}"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CfgFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl CfgFormat {
+    /// The order formats are tried in for files without a known extension
+    const DETECT_ORDER: [Self; 3] = [Self::Toml, Self::Json, Self::Yaml];
+
+    /// `None` when the extension is missing or isn't one of the known formats
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Some(Self::Toml),
+            Some("json") => Some(Self::Json),
+            Some("yaml" | "yml") => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Parse `s` using the format matching `path`'s extension or, when that
+    /// isn't known, the first of [`Self::DETECT_ORDER`] that produces services
+    fn parse_file(path: &Path, s: &str) -> Result<(Self, ServiceDocument), String> {
+        if let Some(format) = Self::from_path(path) {
+            return Ok((format, format.parse(s)?));
+        }
+        let mut errors = Vec::new();
+        for format in Self::DETECT_ORDER {
+            match format.parse(s) {
+                Ok(doc) => {
+                    tracing::debug!("detected `{}` as {}", path.display(), format.name());
+                    return Ok((format, doc));
+                }
+                Err(e) => errors.push(format!("as {}: {e}", format.name())),
+            }
+        }
+        Err(format!(
+            "not a valid toml, json or yaml service file\n{}",
+            errors.join("\n")
+        ))
+    }
+
+    /// `None` for media types other than json, toml and yaml, any parameters
+    /// like `charset` are ignored
+    fn from_content_type(value: &str) -> Option<Self> {
+        let media_type = value.split(';').next()?.trim().to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/toml" | "text/toml" => Some(Self::Toml),
+            "application/json" => Some(Self::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(Self::Yaml)
+            }
+            _ => None,
+        }
+    }
+
+    /// Also the extension used for files written in this format
+    fn name(self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, s: &str) -> Result<T, String> {
+        match self {
+            Self::Toml => toml::from_str(s).map_err(|e| e.to_string()),
+            Self::Json => serde_json::from_str(s).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml::from_str(s).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn serialize(self, value: &impl Serialize) -> Result<String, String> {
+        match self {
+            Self::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+            Self::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Service urls need to be absolute http(s) urls, or a path starting with `/` for
+/// services reverse proxied on this host
+fn validate_url(url: &str) -> Result<(), String> {
+    if url.starts_with('/') && !url.starts_with("//") {
+        return Ok(());
+    }
+    let parsed = url::Url::parse(url).map_err(|e| format!("has an invalid url `{url}`: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "has an unsupported url scheme `{}` in `{url}`",
+            parsed.scheme()
+        ));
+    }
+    Ok(())
+}
+
+/// Replace any `${VAR}` tokens with the value of that environment variable,
+/// `$$` is an escaped literal `$`. Unknown variables are left untouched.
+fn expand_env(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find('$') {
+        ret.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            ret.push('$');
+            rest = after;
+            continue;
+        }
+        let Some(end) = rest.strip_prefix("${").and_then(|after| after.find('}')) else {
+            ret.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let token = &rest[..end + 3];
+        let name = &token[2..token.len() - 1];
+        match std::env::var(name) {
+            Ok(v) => ret.push_str(&v),
+            Err(e) => {
+                tracing::warn!("unable to expand `{token}`: {e}");
+                ret.push_str(token);
+            }
+        }
+        rest = &rest[token.len()..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+fn err(e: impl Display, context: impl Display) -> ResponsePair {
+    err_with_status(StatusCode::INTERNAL_SERVER_ERROR, e, context)
+}
+
+fn err_with_status(status: StatusCode, e: impl Display, context: impl Display) -> ResponsePair {
+    tracing::warn!("Generating error html:\n{e}\n{context}");
+    (
+        status,
+        Html(
+            templates::error()
+                .replace("{{status}}", &escape_html(&status.to_string()))
+                .replace(
+                    "{{timestamp}}",
+                    &humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+                )
+                .replace("{{context}}", &escape_html(&context.to_string()))
+                .replace("{{e}}", &escape_html(&e.to_string())),
+        ),
+    )
+}
+
+async fn sse(headers: HeaderMap) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    tracing::debug!("GET: /sse");
+    // subscribe before reading the cfg for a refresh so no update can slip
+    // between the two
+    let updates = BroadcastStream::new(watcher::subscribe());
+    let refresh = match headers.get(LAST_EVENT_ID) {
+        Some(id) => {
+            tracing::debug!("sse client reconnected after event {id:?}, sending refresh");
+            catch_up_events().await
+        }
+        None => Vec::new(),
+    };
+    let updates = updates
+        .then(|update| async move {
+            let events = match update {
+                Ok(update) => update_events(&update),
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    tracing::warn!("sse client lagged by {skipped} updates, sending catch up");
+                    catch_up_events().await
+                }
+            };
+            futures::stream::iter(events)
+        })
+        .flatten();
+    let stream = futures::stream::iter(refresh)
+        .chain(updates)
+        .map(|event| Ok(event.id(next_event_id().to_string())))
+        .take_until(shutdown_requested());
+    Sse::new(stream).keep_alive(sse_keep_alive())
+}
+
+#[derive(Debug, Deserialize)]
+struct PollQuery {
+    since: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct PollResponse {
+    id: u64,
+}
+
+/// A long polling fallback for `/sse`, waits until there has been a cfg change
+/// after `since` and responds with its id. The id is unchanged if nothing
+/// happened within [`POLL_TIMEOUT`]. Without `since`, or with an id that isn't
+/// the latest like after a restart, the latest id is returned immediately.
+async fn poll(Query(query): Query<PollQuery>) -> axum::Json<PollResponse> {
+    // subscribe before checking the version so no update can slip between the two
+    let mut updates = watcher::subscribe();
+    let current = watcher::version();
+    if query.since != Some(current) {
+        return axum::Json(PollResponse { id: current });
+    }
+    let id = tokio::select! {
+        update = updates.recv() => match update {
+            Ok(update) => update.version,
+            Err(_) => watcher::version(),
+        },
+        _ = tokio::time::sleep(POLL_TIMEOUT) => current,
+        _ = shutdown_requested() => current,
+    };
+    axum::Json(PollResponse { id })
+}
+
+/// Event ids are shared by every client and only ever increase
+fn next_event_id() -> u64 {
+    NEXT_EVENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn sse_keep_alive() -> KeepAlive {
+    match parse_keep_alive_secs(std::env::var(ENV_VAR_SSE_KEEPALIVE).ok().as_deref()) {
+        Some(interval) => KeepAlive::new().interval(interval),
+        None => KeepAlive::default(),
+    }
+}
+
+fn parse_keep_alive_secs(value: Option<&str>) -> Option<Duration> {
+    let value = value?;
+    match value.trim().parse::<u64>() {
+        Ok(0) => {
+            tracing::warn!("{ENV_VAR_SSE_KEEPALIVE} must be greater than 0, using the default");
+            None
+        }
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_SSE_KEEPALIVE} `{value}`, using the default: {e}");
+            None
+        }
+    }
+}
+
+fn update_events(update: &watcher::CfgUpdate) -> Vec<Event> {
+    let mut events: Vec<Event> = update
+        .changes
+        .iter()
+        .filter_map(|change| {
+            Event::default()
+                .event(change.kind.event_name())
+                .json_data(change)
+                .inspect_err(|e| tracing::warn!("failed to serialize {change:?}: {e}"))
+                .ok()
+        })
+        .collect();
+    if update.services_html.is_some() {
+        events.extend(reloaded_event());
+    }
+    events.push(match &update.services_html {
+        Some(html) => {
+            tracing::debug!("Sending services event");
+            Event::default().event("services").data(&**html)
+        }
+        None => {
+            tracing::debug!("cfg reload failed, sending update event");
+            Event::default().data("update")
+        }
+    });
+    events
+}
+
+async fn catch_up_events() -> Vec<Event> {
+    match read_cfg_html().await {
+        Ok(html) => reloaded_event()
+            .into_iter()
+            .chain([Event::default().event("services").data(&*html)])
+            .collect(),
+        Err(e) => {
+            tracing::warn!("failed to read cfg for catch up, sending update event: {e}");
+            vec![Event::default().data("update")]
+        }
+    }
+}
+
+/// Carries the rfc 3339 time of the last reload for the footer
+fn reloaded_event() -> Option<Event> {
+    last_reloaded().map(|at| Event::default().event("reloaded").data(at))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct Services {
+    #[serde(rename = "service")]
+    services: Vec<Service>,
+    /// Files that couldn't be read or parsed along with why
+    #[serde(skip)]
+    errors: Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    dashboard: DashboardConfig,
+}
+
+impl Services {
+    /// The services with a name or description containing `query`, ignoring case
+    fn matching(&self, query: &str) -> Services {
+        let query = query.to_lowercase();
+        Services {
+            services: self
+                .services
+                .iter()
+                .filter(|s| {
+                    s.name.to_lowercase().contains(&query)
+                        || s.desc
+                            .as_deref()
+                            .is_some_and(|desc| desc.to_lowercase().contains(&query))
+                })
+                .cloned()
+                .collect(),
+            errors: Vec::new(),
+            dashboard: self.dashboard.clone(),
+        }
+    }
+
+    /// The services with every one of `tags`, ignoring case
+    fn tagged(&self, tags: &[String]) -> Services {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_lowercase()).collect();
+        Services {
+            services: self
+                .services
+                .iter()
+                .filter(|s| {
+                    tags.iter()
+                        .all(|tag| s.tags.iter().any(|t| t.to_lowercase() == *tag))
+                })
+                .cloned()
+                .collect(),
+            errors: Vec::new(),
+            dashboard: self.dashboard.clone(),
+        }
+    }
+
+    /// Drop any services that have been disabled in their cfg
+    fn without_disabled(mut self) -> Self {
+        self.services.retain(|s| s.enabled);
+        self
+    }
+
+    fn errors_html(&self) -> String {
+        if self.errors.is_empty() {
+            return String::new();
+        }
+        let items: String = self
+            .errors
+            .iter()
+            .map(|(path, e)| {
+                format!(
+                    "<li><code>{}</code><pre>{}</pre></li>",
+                    escape_html(&path.display().to_string()),
+                    escape_html(e)
+                )
+            })
+            .collect();
+        format!(
+            r#"<div class="notice cfg-errors" role="alert"><button type="button" class="dismiss" aria-label="Dismiss" onclick="this.parentElement.remove()">&times;</button><strong>Some config files failed to load</strong><ul>{items}</ul></div>"#
+        )
+    }
+
+    /// The enabled services in the order they are listed
+    fn sorted(&self) -> Vec<&Service> {
+        let mut sorted: Vec<&Service> = self.services.iter().filter(|s| s.enabled).collect();
+        sorted.sort_by(|lhs, rhs| self.dashboard.sort_mode.compare(lhs, rhs));
+        sorted
+    }
+
+    /// [`Self::as_html`] for only the services on the requested page followed
+    /// by links to the neighbouring pages. Pages past the end show the last page.
+    fn page_html(&self, query: &ListQuery) -> String {
+        let tagged = self.tagged(&query.tags);
+        let filter = query.filter_html();
+        if !query.tags.is_empty() && tagged.sorted().is_empty() {
+            return format!(
+                r#"{filter}<li class="empty-state">No services have every one of these tags</li>"#
+            );
+        }
+        if !query.is_paginated() {
+            return format!("{filter}{}", tagged.list_html(&query.collapsed));
+        }
+        let sorted = tagged.sorted();
+        let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE).max(1);
+        let pages = sorted.len().div_ceil(per_page).max(1);
+        let page = query.page.unwrap_or(1).clamp(1, pages);
+        let page_cfg = Services {
+            services: sorted
+                .into_iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .cloned()
+                .collect(),
+            errors: Vec::new(),
+            dashboard: self.dashboard.clone(),
+        };
+        let tag_params = escape_html(&query.tag_params());
+        let link = |page: usize, rel: &str, label: &str| {
+            format!(
+                r#"<a href="?page={page}&amp;per_page={per_page}{tag_params}" rel="{rel}">{label}</a>"#
+            )
+        };
+        let prev = if page > 1 {
+            link(page - 1, "prev", "Previous")
+        } else {
+            String::new()
+        };
+        let next = if page < pages {
+            link(page + 1, "next", "Next")
+        } else {
+            String::new()
+        };
+        format!(
+            r#"{filter}{}<li class="pager">{prev}<span>Page {page} of {pages}</span>{next}</li>"#,
+            page_cfg.list_html(&query.collapsed)
+        )
+    }
+
+    fn as_html(&self) -> String {
+        self.list_html(&Collapsed::default())
+    }
+
+    /// Pinned services come first in their own section and aren't repeated
+    /// in their category, the sections in `collapsed` are rendered closed
+    fn list_html(&self, collapsed: &Collapsed) -> String {
+        let (pinned, sorted): (Vec<&Service>, Vec<&Service>) =
+            self.sorted().into_iter().partition(|s| s.pinned);
+        if pinned.is_empty() && sorted.is_empty() {
+            return Self::empty_html();
+        }
+        let mut ret = if pinned.is_empty() {
+            String::new()
+        } else {
+            Self::section_html("Pinned", r#"data-pinned="""#, pinned, !collapsed.pinned)
+        };
+        ret.push_str(&self.grouped_html(sorted, collapsed));
+        ret
+    }
+
+    /// `sorted` grouped under their categories or hosts, or a plain list when
+    /// grouping by category and none of them have one
+    fn grouped_html(&self, sorted: Vec<&Service>, collapsed: &Collapsed) -> String {
+        let group_by = self.dashboard.group_by;
+        if group_by == GroupBy::Category && sorted.iter().all(|s| s.category.is_none()) {
+            return Self::list_items(sorted.into_iter());
+        }
+        let mut categories: BTreeMap<Cow<'_, str>, Vec<&Service>> = BTreeMap::new();
+        let mut other = Vec::new();
+        for service in sorted {
+            match service.group(group_by) {
+                Some(category) => categories.entry(category).or_default().push(service),
+                None => other.push(service),
+            }
+        }
+        let mut ret = String::new();
+        for category in &self.dashboard.category_order {
+            if let Some(services) = categories.remove(category.as_str()) {
+                ret.push_str(&Self::category_html(category, services, collapsed));
+            }
+        }
+        for (category, services) in categories {
+            ret.push_str(&Self::category_html(&category, services, collapsed));
+        }
+        if !other.is_empty() {
+            ret.push_str(&Self::category_html("Other", other, collapsed));
+        }
+        ret
+    }
+
+    fn empty_html() -> String {
+        let cfg_dir = CFG_PATH
+            .get()
+            .and_then(|dirs| dirs.last())
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "cfg".to_string());
+        format!(
+            r#"<li class="empty-state">No services configured yet, add a cfg file to <code>{}</code> to add one</li>"#,
+            escape_html(&cfg_dir)
+        )
+    }
+
+    fn category_html(category: &str, services: Vec<&Service>, collapsed: &Collapsed) -> String {
+        let attr = format!(r#"data-category="{}""#, escape_html(category));
+        let open = !collapsed.categories.contains(category);
+        Self::section_html(category, &attr, services, open)
+    }
+
+    /// `attr` is what `/assets/index.js` uses to tell which section was toggled
+    fn section_html(title: &str, attr: &str, services: Vec<&Service>, open: bool) -> String {
+        let open = if open { " open" } else { "" };
+        format!(
+            r#"<li class="service-category"><details {attr}{open}><summary><h3>{}</h3></summary><ul>{}</ul></details></li>"#,
+            escape_html(title),
+            Self::list_items(services.into_iter())
+        )
+    }
+
+    fn list_items<'a>(services: impl Iterator<Item = &'a Service>) -> String {
+        services
+            .map(Service::as_html)
+            .map(|s| format!("<li>{s}</li>"))
+            .collect::<Vec<_>>()
+            .join("")
+    }
+}
+
+/// The contents of a single config file, either a list of `[[service]]`
+/// tables or the fields of one service at the top level.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ServiceDocument {
+    Many(Box<Services>),
+    One(Box<Service>),
+}
+
+impl ServiceDocument {
+    fn into_services(self) -> Vec<Service> {
+        match self {
+            Self::Many(services) => services.services,
+            Self::One(service) => vec![*service],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Service {
+    /// `title` is accepted for older cfg files
+    #[serde(alias = "title")]
+    name: String,
+    /// `link` is accepted for older cfg files
+    #[serde(alias = "link")]
+    url: String,
+    /// Other addresses the service is reachable at, shown beside the name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    alt_urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    desc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    /// A hex or named css color used as the entry's accent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    new_tab: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sort: Option<i32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    markdown: bool,
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    enabled: bool,
+    /// Listed in a section above every category
+    #[serde(default, skip_serializing_if = "is_default")]
+    pinned: bool,
+    /// Shown on the entry, and the list can be filtered to one or more with `?tag=`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    health: Option<health::HealthCheck>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proxy: Option<proxy::ProxyConfig>,
+    /// The file this service was read from
+    #[serde(skip)]
+    source: Option<PathBuf>,
+    /// When `source` was last modified
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// For `skip_serializing_if`, so rewriting a cfg file doesn't fill it with defaults
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl Service {
+    fn sort_key(&self) -> (i32, &str) {
+        (self.sort.unwrap_or(0), &self.name)
+    }
+
+    /// The section this is listed under, `None` for "Other"
+    fn group(&self, group_by: GroupBy) -> Option<Cow<'_, str>> {
+        match group_by {
+            GroupBy::Category => self.category.as_deref().map(Cow::Borrowed),
+            GroupBy::Host => Some(
+                url::Url::parse(&self.url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| Cow::Owned(host.to_string())))
+                    .unwrap_or(Cow::Borrowed("Local")),
+            ),
+        }
+    }
+
+    fn expand_env(&mut self) {
+        self.name = expand_env(&self.name);
+        self.url = expand_env(&self.url);
+        self.alt_urls = self.alt_urls.iter().map(|url| expand_env(url)).collect();
+        self.desc = self.desc.as_deref().map(expand_env);
+        self.icon = self.icon.as_deref().map(expand_env);
+        self.category = self.category.as_deref().map(expand_env);
+        self.tags = self.tags.iter().map(|tag| expand_env(tag)).collect();
+        if let Some(proxy) = self.proxy.as_mut() {
+            proxy.upstream = expand_env(&proxy.upstream);
+        }
+        if let Some(health) = self.health.as_mut() {
+            for value in health.headers.0.values_mut() {
+                *value = expand_env(value);
+            }
+        }
+    }
+
+    fn as_html(&self) -> String {
+        let name = escape_html(&self.name);
+        let desc = match self.desc.as_deref() {
+            Some(desc) if self.markdown => format!(
+                r#"<div class="service-desc">{}</div>"#,
+                markdown::render(desc)
+            ),
+            Some(desc) => format!("<span>{}</span>", markdown::linkify(desc)),
+            None => String::new(),
+        };
+        let url = escape_js_attr(&self.url);
+        let goto_args = if self.new_tab {
+            format!("'{url}', true")
+        } else {
+            format!("'{url}'")
+        };
+        let icon = self
+            .icon
+            .as_deref()
+            .map(|icon| {
+                format!(
+                    r#"<img class="service-icon" src="{}" alt="" />"#,
+                    escape_html(icon)
+                )
+            })
+            .unwrap_or_default();
+        let status = health::status_of(&self.name);
+        let status = format!(
+            r#"<span class="status-dot {}" title="{}"></span>"#,
+            health::Status::css_class(status),
+            escape_html(&health::status_title(&self.name, status)),
+        );
+        let sparkline = health::sparkline_html(&self.name);
+        let target = if self.new_tab {
+            r#" target="_blank" rel="noopener""#
+        } else {
+            ""
+        };
+        let alt_urls: String = self
+            .alt_urls
+            .iter()
+            .map(|url| {
+                let label = url::Url::parse(url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(String::from))
+                    .unwrap_or_else(|| url.clone());
+                format!(
+                    r#"<a class="alt-url" href="{}"{target}>{}</a>"#,
+                    escape_html(url),
+                    escape_html(&label)
+                )
+            })
+            .collect();
+        // the name is a real link so the entry can be reached with the keyboard,
+        // clicking anywhere else on the entry is handled by `goto`
+        let href = escape_html(&self.url);
+        let label = match self.desc.as_deref() {
+            Some(desc) if !self.markdown => escape_html(&format!("{}, {desc}", self.name)),
+            _ => name.clone(),
+        };
+        let alt_urls = if alt_urls.is_empty() {
+            alt_urls
+        } else {
+            format!(r#"<div class="alt-urls">{alt_urls}</div>"#)
+        };
+        let tags: String = self
+            .tags
+            .iter()
+            .map(|tag| {
+                let param: String = url::form_urlencoded::byte_serialize(tag.as_bytes()).collect();
+                format!(
+                    r#"<a class="tag" href="/?tag={}">{}</a>"#,
+                    escape_html(&param),
+                    escape_html(tag)
+                )
+            })
+            .collect();
+        let tags = if tags.is_empty() {
+            tags
+        } else {
+            format!(r#"<div class="tags">{tags}</div>"#)
+        };
+        let style = self
+            .color
+            .as_deref()
+            .map(|color| format!(r#" style="--accent: {color}""#))
+            .unwrap_or_default();
+        let updated = self
+            .modified
+            .map(|modified| {
+                let timestamp = humantime::format_rfc3339_seconds(modified).to_string();
+                format!(r#" title="updated {}""#, &timestamp[..10])
+            })
+            .unwrap_or_default();
+        format!(
+            r#"<article class="service-entry"{style}{updated} onclick="goto({goto_args})">{icon}<h2>{status}<a class="service-link" href="{href}"{target} aria-label="{label}">{name}</a></h2>{alt_urls}{desc}{tags}{sparkline}</article>"#
+        )
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            _ => ret.push(ch),
+        }
+    }
+    ret
+}
+
+/// Escape a value for use inside a single quoted js string literal that
+/// is itself inside of an html attribute, e.g. `onclick="goto('{value}')"`.
+///
+/// The browser decodes the attribute before handing it to the js parser so
+/// the js escapes need to be applied first.
+fn escape_js_attr(s: &str) -> String {
+    let mut js = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => js.push_str("\\\\"),
+            '\'' => js.push_str("\\'"),
+            '"' => js.push_str("\\\""),
+            '\n' => js.push_str("\\n"),
+            '\r' => js.push_str("\\r"),
+            '\u{2028}' => js.push_str("\\u2028"),
+            '\u{2029}' => js.push_str("\\u2029"),
+            _ => js.push(ch),
+        }
+    }
+    escape_html(&js)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(toml: &str) -> Service {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn services(toml: &str) -> Services {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// Read `dirs` the way [`load_cfg`] does, without the cache
+    async fn load_dirs(dirs: &[PathBuf]) -> Services {
+        read_listings(&list_cfg_dirs(dirs).await.unwrap()).await
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn names(services: &Services) -> Vec<&str> {
+        services.services.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn escape_html_escapes_markup_and_quotes() {
+        assert_eq!(
+            escape_html(r#"<a href="x" title='y'>&</a>"#),
+            "&lt;a href=&quot;x&quot; title=&#39;y&#39;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn service_html_escapes_every_field() {
+        let html = service(
+            r#"
+            name = "<script>alert(1)</script>"
+            url = "http://example.com/?a=1&b='2'"
+            desc = "<b>bold</b>"
+            icon = "/assets/\"icon\".png"
+            "#,
+        )
+        .as_html();
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
+        assert!(html.contains(r#"src="/assets/&quot;icon&quot;.png""#));
+        assert!(html.contains(r#"href="http://example.com/?a=1&amp;b=&#39;2&#39;""#));
+    }
+
+    #[test]
+    fn escape_js_attr_escapes_for_js_then_html() {
+        assert_eq!(escape_js_attr(r#"a'b"c\d"#), r#"a\&#39;b\&quot;c\\d"#);
+        assert_eq!(escape_js_attr("a\nb\u{2028}"), "a\\nb\\u2028");
+    }
+
+    #[tokio::test]
+    async fn reads_toml_and_yaml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"toml\"\nurl = \"http://a.local\"\n",
+        );
+        write(dir.path(), "b.yml", "name: yml\nurl: http://b.local\n");
+        write(dir.path(), "c.yaml", "name: yaml\nurl: http://c.local\n");
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert!(services.errors.is_empty(), "{:?}", services.errors);
+        assert_eq!(names(&services), ["toml", "yml", "yaml"]);
+        assert_eq!(services.services[1].url, "http://b.local");
+    }
+
+    #[tokio::test]
+    async fn reads_many_services_from_one_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "many.toml",
+            r#"
+            [[service]]
+            name = "one"
+            url = "http://one.local"
+
+            [[service]]
+            name = "two"
+            url = "http://two.local"
+            "#,
+        );
+        write(
+            dir.path(),
+            "single.toml",
+            "name = \"three\"\nurl = \"http://three.local\"\n",
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert!(services.errors.is_empty(), "{:?}", services.errors);
+        assert_eq!(names(&services), ["one", "two", "three"]);
+    }
+
+    #[tokio::test]
+    async fn reads_nested_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "top.toml",
+            "name = \"top\"\nurl = \"http://top.local\"\n",
+        );
+        write(
+            dir.path(),
+            "media/video/deep.toml",
+            "name = \"deep\"\nurl = \"http://deep.local\"\n",
+        );
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("media/loop")).unwrap();
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert!(services.errors.is_empty(), "{:?}", services.errors);
+        let mut found = names(&services);
+        found.sort();
+        assert_eq!(found, ["deep", "top"]);
+    }
+
+    #[test]
+    fn expand_env_replaces_known_vars() {
+        std::env::set_var("HOME_SERVICES_TEST_HOST", "nas.local");
+        assert_eq!(
+            expand_env("http://${HOME_SERVICES_TEST_HOST}:8080"),
+            "http://nas.local:8080"
+        );
+        assert_eq!(expand_env("costs $$5"), "costs $5");
+        assert_eq!(
+            expand_env("${HOME_SERVICES_TEST_UNSET} and $ and ${unclosed"),
+            "${HOME_SERVICES_TEST_UNSET} and $ and ${unclosed"
+        );
+    }
+
+    #[tokio::test]
+    async fn expands_env_vars_in_cfg_files() {
+        std::env::set_var("HOME_SERVICES_TEST_PORT", "9000");
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "env.toml",
+            "name = \"env\"\nurl = \"http://localhost:${HOME_SERVICES_TEST_PORT}\"\n",
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(services.services[0].url, "http://localhost:9000");
+    }
+
+    #[tokio::test]
+    async fn cache_is_stale_after_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path().to_path_buf()];
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\n",
+        );
+        let listings = list_cfg_dirs(&dirs).await.unwrap();
+        let cached = CachedCfg {
+            services: read_listings(&listings).await,
+            newest: listings.iter().filter_map(|l| l.newest).max(),
+            file_count: listings.iter().map(|l| l.files.len()).sum(),
+            html: RwLock::new(None),
+        };
+        assert!(cached.is_fresh(&list_cfg_dirs(&dirs).await.unwrap()));
+
+        let file = std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("a.toml"))
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(!cached.is_fresh(&list_cfg_dirs(&dirs).await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn cache_is_stale_after_a_file_is_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path().to_path_buf()];
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\n",
+        );
+        let listings = list_cfg_dirs(&dirs).await.unwrap();
+        let cached = CachedCfg {
+            services: read_listings(&listings).await,
+            // a new file with the same mtime must still be noticed
+            newest: Some(SystemTime::now() + Duration::from_secs(60)),
+            file_count: listings.iter().map(|l| l.files.len()).sum(),
+            html: RwLock::new(None),
+        };
+        write(
+            dir.path(),
+            "b.toml",
+            "name = \"b\"\nurl = \"http://b.local\"\n",
+        );
+        assert!(!cached.is_fresh(&list_cfg_dirs(&dirs).await.unwrap()));
+    }
+
+    #[test]
+    fn icon_is_rendered_only_when_set() {
+        let with = service("name = \"a\"\nurl = \"http://a.local\"\nicon = \"/assets/a.png\"\n");
+        assert!(with
+            .as_html()
+            .contains(r#"<img class="service-icon" src="/assets/a.png" alt="" />"#));
+        let without = service("name = \"a\"\nurl = \"http://a.local\"\n");
+        assert!(!without.as_html().contains("<img"));
+    }
+
+    #[test]
+    fn new_tab_services_open_in_a_new_tab() {
+        let html = service("name = \"a\"\nurl = \"http://a.local\"\nnew_tab = true\n").as_html();
+        assert!(html.contains(r#"onclick="goto('http://a.local', true)""#));
+        assert!(html.contains(r#"target="_blank" rel="noopener""#));
+        let html = service("name = \"a\"\nurl = \"http://a.local\"\n").as_html();
+        assert!(html.contains(r#"onclick="goto('http://a.local')""#));
+        assert!(!html.contains("_blank"));
+    }
+
+    #[test]
+    fn services_are_grouped_under_their_category() {
+        let html = services(
+            r#"
+            [[service]]
+            name = "plex"
+            url = "http://plex.local"
+            category = "Media"
+
+            [[service]]
+            name = "router"
+            url = "http://router.local"
+
+            [[service]]
+            name = "grafana"
+            url = "http://grafana.local"
+            category = "Monitoring"
+            "#,
+        )
+        .as_html();
+        let media = html.find("<h3>Media</h3>").unwrap();
+        let monitoring = html.find("<h3>Monitoring</h3>").unwrap();
+        let other = html.find("<h3>Other</h3>").unwrap();
+        assert!(media < html.find("plex").unwrap());
+        assert!(media < monitoring && monitoring < other);
+        assert!(html[monitoring..other].contains("grafana"));
+        assert!(html[other..].contains("router"));
+    }
+
+    #[test]
+    fn services_without_categories_are_a_plain_list() {
+        let html = services(
+            r#"
+            [[service]]
+            name = "router"
+            url = "http://router.local"
+            "#,
+        )
+        .as_html();
+        assert!(!html.contains("service-category"));
+        assert!(html.starts_with("<li><article"));
+    }
+
+    #[test]
+    fn search_matches_names_and_descriptions_ignoring_case() {
+        let cfg = services(
+            r#"
+            [[service]]
+            name = "Plex"
+            url = "http://plex.local"
+
+            [[service]]
+            name = "nas"
+            url = "http://nas.local"
+            desc = "Backups and PLEX media"
+
+            [[service]]
+            name = "router"
+            url = "http://router.local"
+            "#,
+        );
+        assert_eq!(names(&cfg.matching("plex")), ["Plex", "nas"]);
+        assert_eq!(names(&cfg.matching("ROUTER")), ["router"]);
+        assert!(cfg.matching("printer").services.is_empty());
+    }
+
+    #[test]
+    fn services_are_ordered_by_sort_then_name() {
+        let cfg = services(
+            r#"
+            [[service]]
+            name = "b"
+            url = "http://b.local"
+
+            [[service]]
+            name = "last"
+            url = "http://last.local"
+            sort = 10
+
+            [[service]]
+            name = "a"
+            url = "http://a.local"
+
+            [[service]]
+            name = "first"
+            url = "http://first.local"
+            sort = -1
+            "#,
+        );
+        let sorted: Vec<&str> = cfg.sorted().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(sorted, ["first", "a", "b", "last"]);
+    }
+
+    #[test]
+    fn bind_addr_accepts_v4_and_v6_and_falls_back() {
+        assert_eq!(parse_bind_addr(None), DEFAULT_BIND);
+        assert_eq!(
+            parse_bind_addr(Some(" 127.0.0.1:9000 ")),
+            "127.0.0.1:9000".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_bind_addr(Some("[::]:8080")),
+            "[::]:8080".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(parse_bind_addr(Some("localhost")), DEFAULT_BIND);
+        assert_eq!(parse_bind_addr(Some("127.0.0.1:99999")), DEFAULT_BIND);
+    }
+
+    #[tokio::test]
+    async fn shutdown_finishes_open_requests_then_stops_accepting() {
+        let app = Router::new().route(
+            "/slow",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                "done"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_listener(listener, app, async {
+            let _ = rx.await;
+        }));
+        let in_flight = tokio::spawn(reqwest::get(format!("http://{addr}/slow")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.send(()).unwrap();
+        let res = in_flight.await.unwrap().unwrap();
+        assert_eq!(res.text().await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn sse_pushes_the_rendered_services_list() {
+        let _cfg = test_support::lock_cfg().await;
+        let res = test_support::get("/sse").await;
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+        watcher::reload(Vec::new()).await.unwrap();
+        let text = test_support::read_until(res, &["event: services", "plex.local"]).await;
+        let services = text
+            .lines()
+            .skip_while(|line| *line != "event: services")
+            .find(|line| line.starts_with("data: "))
+            .unwrap();
+        assert!(services.contains("<a"), "{services}");
+        assert!(!text.contains("data: update"), "{text}");
+    }
+
+    #[tokio::test]
+    async fn deleted_files_drop_their_services() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path().to_path_buf()];
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\n",
+        );
+        write(
+            dir.path(),
+            "b.toml",
+            "name = \"b\"\nurl = \"http://b.local\"\n",
+        );
+        let listings = list_cfg_dirs(&dirs).await.unwrap();
+        let cached = CachedCfg {
+            services: read_listings(&listings).await,
+            newest: listings.iter().filter_map(|l| l.newest).max(),
+            file_count: listings.iter().map(|l| l.files.len()).sum(),
+            html: RwLock::new(None),
+        };
+        std::fs::remove_file(dir.path().join("b.toml")).unwrap();
+        assert!(!cached.is_fresh(&list_cfg_dirs(&dirs).await.unwrap()));
+        assert_eq!(names(&load_dirs(&dirs).await), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn broken_files_are_listed_in_a_banner() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "good.toml",
+            "name = \"good\"\nurl = \"http://good.local\"\n",
+        );
+        write(dir.path(), "bad.toml", "name = \"bad\nurl = 1\n");
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(names(&services), ["good"]);
+        assert_eq!(services.errors.len(), 1);
+        let banner = services.errors_html();
+        assert!(banner.contains("role=\"alert\""), "{banner}");
+        assert!(banner.contains("bad.toml"), "{banner}");
+        assert!(!banner.contains("good.toml"), "{banner}");
+    }
+
+    #[test]
+    fn urls_must_be_http_or_a_local_path() {
+        assert!(validate_url("https://grafana.local:3000/d").is_ok());
+        assert!(validate_url("/grafana").is_ok());
+        assert!(validate_url("htp://foo")
+            .unwrap_err()
+            .contains("unsupported url scheme"));
+        assert!(validate_url("//evil.example").is_err());
+        assert!(validate_url("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn services_with_invalid_urls_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "typo.toml",
+            "name = \"typo\"\nurl = \"htp://foo\"\n",
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert!(services.services.is_empty());
+        assert!(services.errors[0].0.ends_with("typo.toml"));
+    }
+
+    #[test]
+    fn args_are_parsed() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+        let args = Args::try_parse_from([
+            "home-services",
+            "--cfg-dir",
+            "/srv/cfg",
+            "--assets-dir",
+            "/srv/assets",
+            "--bind",
+            "127.0.0.1:9000",
+            "--check",
+        ])
+        .unwrap();
+        assert_eq!(args.cfg_dir, "/srv/cfg");
+        assert_eq!(args.assets_dir, Path::new("/srv/assets"));
+        assert_eq!(args.bind.as_deref(), Some("127.0.0.1:9000"));
+        assert!(args.check);
+        assert!(Args::try_parse_from(["home-services", "--nope"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn sse_events_name_the_changed_file() {
+        let _cfg = test_support::lock_cfg().await;
+        let res = test_support::get("/sse").await;
+        watcher::reload(vec![watcher::Change {
+            changed: Some("media/plex.toml".to_string()),
+            kind: watcher::ChangeKind::Modify,
+        }])
+        .await
+        .unwrap();
+        let text = test_support::read_until(
+            res,
+            &[r#"data: {"changed":"media/plex.toml","kind":"modify"}"#],
+        )
+        .await;
+        assert!(text.contains("event: modified"), "{text}");
+    }
+
+    #[tokio::test]
+    async fn every_sse_client_gets_each_update() {
+        let _cfg = test_support::lock_cfg().await;
+        let first = test_support::get("/sse").await;
+        let second = test_support::get("/sse").await;
+        watcher::reload(vec![watcher::Change {
+            changed: Some("broadcast.toml".to_string()),
+            kind: watcher::ChangeKind::Create,
+        }])
+        .await
+        .unwrap();
+        let needles = [r#""changed":"broadcast.toml""#];
+        let (first, second) = tokio::join!(
+            test_support::read_until(first, &needles),
+            test_support::read_until(second, &needles),
+        );
+        assert!(first.contains("event: created"), "{first}");
+        assert!(second.contains("event: created"), "{second}");
+    }
+
+    #[test]
+    fn keep_alive_secs_must_be_a_positive_number() {
+        assert_eq!(parse_keep_alive_secs(None), None);
+        assert_eq!(
+            parse_keep_alive_secs(Some(" 5 ")),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(parse_keep_alive_secs(Some("0")), None);
+        assert_eq!(parse_keep_alive_secs(Some("soon")), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_get_a_not_found_page() {
+        let res = test_support::get("/does-not-exist").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = test_support::text(res).await;
+        assert!(body.contains("<h1>Not Found</h1>"), "{body}");
+        assert!(body.contains("<code>/does-not-exist</code>"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn not_found_escapes_the_path() {
+        let (_, Html(body)) = not_found(Uri::from_static("/a&b=1")).await;
+        assert!(body.contains("<code>/a&amp;b=1</code>"), "{body}");
+    }
+
+    #[test]
+    fn error_page_fills_in_every_placeholder() {
+        let (status, Html(body)) = err("boom <b>", "while testing");
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("boom &lt;b&gt;"), "{body}");
+        assert!(body.contains("while testing"), "{body}");
+        assert!(body.contains("500 Internal Server Error"), "{body}");
+        assert!(!body.contains("{{"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn dashboard_settings_brand_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            dashboard::DASHBOARD_FILE,
+            "title = \"Lab <1>\"\nlogo = \"/assets/logo.png\"\naccent_color = \"#0d47a1\"\n",
+        );
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\n",
+        );
+        let cfg = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(names(&cfg), ["a"]);
+        let html = render_index(&cfg, Theme::Light, "");
+        assert!(html.contains("<title>Lab &lt;1&gt;</title>"), "{html}");
+        assert!(html.contains(r#"src="/assets/logo.png""#), "{html}");
+        assert!(html.contains("--accent: #0d47a1;"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn the_dashboard_file_is_optional() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = load_dirs(&[dir.path().to_path_buf()]).await;
+        let html = render_index(&cfg, Theme::Light, "");
+        assert!(html.contains("<title>Home Services</title>"), "{html}");
+        assert!(!html.contains(r#"<img class="dashboard-logo""#), "{html}");
+    }
+
+    #[tokio::test]
+    async fn theme_sets_a_cookie_and_redirects_back() {
+        let res = test_support::get("/theme?mode=dark").await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(res.headers()[header::LOCATION], "/");
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("theme=dark;"), "{cookie}");
+        assert!(cookie.contains("Path=/"), "{cookie}");
+    }
+
+    #[tokio::test]
+    async fn index_follows_the_theme_cookie() {
+        let default = test_support::text(test_support::get("/").await).await;
+        assert!(default.contains(r#"<body data-theme="light">"#));
+        let req = Request::get("/")
+            .header(header::COOKIE, "theme=dark")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let dark = test_support::text(test_support::send(req).await).await;
+        assert!(dark.contains(r#"<body data-theme="dark">"#));
+        assert!(dark.contains(r#"href="/theme?mode=light""#));
+    }
+
+    #[tokio::test]
+    async fn favicon_is_served_instead_of_the_index() {
+        let res = test_support::get("/favicon.ico").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "image/x-icon");
+        assert_eq!(
+            res.headers()[header::CACHE_CONTROL],
+            "public, max-age=604800"
+        );
+        assert_eq!(test_support::bytes(res).await, FAVICON);
+    }
+
+    #[tokio::test]
+    async fn assets_are_served_from_the_assets_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "hello.txt", "hi from the assets dir");
+        let req = Request::get("/assets/hello.txt")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = app(dir.path()).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "public, max-age=3600");
+        assert_eq!(test_support::text(res).await, "hi from the assets dir");
+    }
+
+    #[tokio::test]
+    async fn disabled_services_are_not_shown() {
+        let html = test_support::text(test_support::get("/").await).await;
+        assert!(html.contains("http://plex.local"), "{html}");
+        assert!(!html.contains("http://hidden.local"), "{html}");
+        let cfg = services(
+            r#"
+            [[service]]
+            name = "on"
+            url = "http://on.local"
+
+            [[service]]
+            name = "off"
+            url = "http://off.local"
+            enabled = false
+            "#,
+        );
+        let html = cfg.as_html();
+        assert!(html.contains("http://on.local"));
+        assert!(!html.contains("http://off.local"));
+    }
+
+    #[tokio::test]
+    async fn responses_are_compressed_except_sse() {
+        let get_gzip = |uri: &str| {
+            Request::get(uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        let res = test_support::send(get_gzip("/")).await;
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        let res = test_support::send(get_gzip("/api/services")).await;
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        let res = test_support::send(get_gzip("/sse")).await;
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    /// A writer keeping everything logged for the test to inspect
+    #[derive(Clone, Default)]
+    struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_with(json: bool) -> String {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = subscriber(
+            json,
+            EnvFilter::new("info"),
+            BoxMakeWriter::new(move || writer.clone()),
+            None,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("request", id = "abc").entered();
+            tracing::info!(service = "plex", "checked");
+        });
+        let bytes = captured.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn json_logs_are_one_object_per_line_with_spans() {
+        assert!(log_format_is_json(Some(" JSON ")));
+        assert!(!log_format_is_json(Some("text")));
+        assert!(!log_format_is_json(None));
+        let out = log_with(true);
+        let line: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!(line["fields"]["message"], "checked");
+        assert_eq!(line["fields"]["service"], "plex");
+        assert_eq!(line["span"]["id"], "abc");
+        assert_eq!(line["spans"][0]["name"], "request");
+    }
+
+    #[test]
+    fn text_logs_are_not_json() {
+        let out = log_with(false);
+        assert!(out.contains("checked"), "{out}");
+        assert!(serde_json::from_str::<serde_json::Value>(out.trim()).is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnecting_sse_clients_get_a_refresh() {
+        let req = Request::get("/sse")
+            .header(LAST_EVENT_ID, "41")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = test_support::send(req).await;
+        let text = test_support::read_until(res, &["event: services", "plex.local"]).await;
+        let ids: Vec<u64> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("id: "))
+            .map(|id| id.parse().unwrap())
+            .collect();
+        assert!(!ids.is_empty(), "{text}");
+        assert!(ids.windows(2).all(|w| w[0] < w[1]), "{text}");
+    }
+
+    #[test]
+    fn no_services_shows_an_empty_state() {
+        let cfg = Services::default();
+        let html = cfg.as_html();
+        assert!(html.contains("No services configured yet"), "{html}");
+        assert!(html.contains("add a cfg file to"), "{html}");
+        let json = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(json, serde_json::json!({ "service": [] }));
+        let only_disabled = services("[[service]]\nname = \"a\"\nurl = \"/a\"\nenabled = false\n");
+        assert!(only_disabled
+            .as_html()
+            .contains("No services configured yet"));
+    }
+
+    /// The `data-category` of each section in the order they are rendered
+    fn sections(html: &str) -> Vec<&str> {
+        html.split(r#"data-category=""#)
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect()
+    }
+
+    #[test]
+    fn category_order_comes_first_then_alphabetical_then_other() {
+        let mut cfg = services(
+            r#"
+            [[service]]
+            name = "a"
+            url = "/a"
+            category = "Alpha"
+
+            [[service]]
+            name = "b"
+            url = "/b"
+            category = "Beta"
+
+            [[service]]
+            name = "c"
+            url = "/c"
+            category = "Gamma"
+
+            [[service]]
+            name = "d"
+            url = "/d"
+            "#,
+        );
+        cfg.dashboard.category_order = vec!["Gamma".to_string(), "Missing".to_string()];
+        assert_eq!(
+            sections(&cfg.as_html()),
+            ["Gamma", "Alpha", "Beta", "Other"]
+        );
+    }
+
+    #[tokio::test]
+    async fn fragment_is_the_list_without_the_page() {
+        let res = test_support::get("/fragment/services").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+        let html = test_support::text(res).await;
+        assert!(html.contains("<li"), "{html}");
+        assert!(html.contains("http://plex.local"), "{html}");
+        assert!(!html.contains("<html"), "{html}");
+        assert!(!html.contains("<body"), "{html}");
+    }
+
+    #[test]
+    fn flags_accept_common_spellings() {
+        for on in ["1", "true", " YES "] {
+            assert!(parse_flag(ENV_VAR_STRICT, Some(on)), "{on}");
+        }
+        for off in ["0", "false", "no", "", "maybe"] {
+            assert!(!parse_flag(ENV_VAR_STRICT, Some(off)), "{off}");
+        }
+        assert!(!parse_flag(ENV_VAR_STRICT, None));
+    }
+
+    #[tokio::test]
+    async fn strict_mode_fails_on_a_bad_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "good.toml",
+            "name = \"good\"\nurl = \"http://good.local\"\n",
+        );
+        let dirs = [dir.path().to_path_buf()];
+        assert_eq!(require_no_errors(&load_dirs(&dirs).await), Ok(()));
+        write(
+            dir.path(),
+            "bad.toml",
+            "name = \"bad\"\nurl = \"htp://bad\"\n",
+        );
+        assert_eq!(
+            require_no_errors(&load_dirs(&dirs).await),
+            Err("1 cfg error(s)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn health_is_always_ok() {
+        let res = test_support::get("/health").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test_support::text(res).await, "ok");
+    }
+
+    #[tokio::test]
+    async fn ready_once_the_cfg_has_been_read() {
+        assert_eq!(readiness_for(false).0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(test_support::get("/").await.status(), StatusCode::OK);
+        let res = test_support::get("/ready").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test_support::text(res).await, "ready");
+    }
+
+    #[tokio::test]
+    async fn index_is_not_modified_for_a_matching_etag() {
+        let _cfg = test_support::lock_cfg().await;
+        let res = test_support::get("/").await;
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        let etag = res.headers()[header::ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with("W/\""), "{etag:?}");
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, etag.clone())
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = test_support::send(req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], etag);
+        assert!(test_support::bytes(res).await.is_empty());
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, "W/\"stale\"")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(test_support::send(req).await.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn if_none_match_accepts_lists_and_wildcards() {
+        let etag = etag_for("body");
+        let with = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+            if_none_match(&headers, &etag)
+        };
+        assert!(with(&etag));
+        assert!(with(&format!("W/\"other\", {etag}")));
+        assert!(with("*"));
+        assert!(!with("W/\"other\""));
+        assert!(!if_none_match(&HeaderMap::new(), &etag));
+    }
+
+    #[tokio::test]
+    async fn later_cfg_dirs_override_earlier_ones() {
+        let shared = tempfile::tempdir().unwrap();
+        let personal = tempfile::tempdir().unwrap();
+        write(
+            shared.path(),
+            "services.toml",
+            r#"
+            [[service]]
+            name = "grafana"
+            url = "http://shared.local/grafana"
+
+            [[service]]
+            name = "plex"
+            url = "http://plex.local"
+            "#,
+        );
+        write(
+            personal.path(),
+            "grafana.toml",
+            "name = \"grafana\"\nurl = \"http://mine.local/grafana\"\n",
+        );
+        let cfg = load_dirs(&[shared.path().to_path_buf(), personal.path().to_path_buf()]).await;
+        let mut names = names(&cfg);
+        names.sort();
+        assert_eq!(names, ["grafana", "plex"]);
+        let grafana = cfg.services.iter().find(|s| s.name == "grafana").unwrap();
+        assert_eq!(grafana.url, "http://mine.local/grafana");
+    }
+
+    #[test]
+    fn cfg_dirs_are_split_like_path() {
+        let joined = std::env::join_paths(["/etc/home-services", "/home/me/.config/hs"]).unwrap();
+        assert_eq!(
+            parse_cfg_dirs(&joined),
+            [
+                PathBuf::from("/etc/home-services"),
+                PathBuf::from("/home/me/.config/hs")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn services_carry_their_file_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\n",
+        );
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_200_000);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("a.toml"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let cfg = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(cfg.services[0].modified, Some(mtime));
+        assert!(cfg.services[0]
+            .as_html()
+            .contains(r#"title="updated 2024-06-01""#));
+        let unread = service("name = \"b\"\nurl = \"http://b.local\"\n");
+        assert!(!unread.as_html().contains("updated"));
+    }
+
+    #[test]
+    fn legacy_field_names_are_accepted() {
+        let legacy = service("title = \"Grafana\"\nlink = \"http://grafana.local\"\n");
+        assert_eq!(legacy.name, "Grafana");
+        assert_eq!(legacy.url, "http://grafana.local");
+        let json = serde_json::to_value(&legacy).unwrap();
+        assert_eq!(json["name"], "Grafana");
+        assert_eq!(json["url"], "http://grafana.local");
+    }
+
+    #[test]
+    fn desc_is_optional() {
+        let without = service("name = \"a\"\nurl = \"http://a.local\"\n");
+        assert!(!without.as_html().contains("<span>"));
+        assert!(serde_json::to_value(&without)
+            .unwrap()
+            .get("desc")
+            .is_none());
+        let with = service("name = \"a\"\nurl = \"http://a.local\"\ndesc = \"media\"\n");
+        assert!(with.as_html().contains("<span>media</span>"));
+        assert_eq!(serde_json::to_value(&with).unwrap()["desc"], "media");
+    }
+
+    fn five_services() -> Services {
+        let toml: String = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| format!("[[service]]\nname = \"{name}\"\nurl = \"http://{name}.local\"\n"))
+            .collect();
+        services(&toml)
+    }
+
+    /// The hosts of the services linked in `html`, in order
+    fn linked(html: &str) -> Vec<&str> {
+        html.split(r#"class="service-link" href="http://"#)
+            .skip(1)
+            .filter_map(|rest| rest.split(".local").next())
+            .collect()
+    }
+
+    fn page(query: &str) -> String {
+        five_services().page_html(&ListQuery::parse(Some(query), &HeaderMap::new()))
+    }
+
+    #[test]
+    fn pages_slice_the_sorted_services() {
+        let html = page("per_page=2&page=2");
+        assert_eq!(linked(&html), ["c", "d"]);
+        assert!(
+            html.contains(r#"href="?page=1&amp;per_page=2" rel="prev""#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"href="?page=3&amp;per_page=2" rel="next""#),
+            "{html}"
+        );
+        assert!(html.contains("Page 2 of 3"), "{html}");
+        let last = page("per_page=2&page=9");
+        assert_eq!(linked(&last), ["e"]);
+        assert!(!last.contains(r#"rel="next""#), "{last}");
+    }
+
+    #[test]
+    fn no_pagination_params_shows_everything() {
+        let html = page("");
+        assert_eq!(linked(&html), ["a", "b", "c", "d", "e"]);
+        assert!(!html.contains("pager"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn check_reports_each_bad_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path().to_path_buf()];
+        write(
+            dir.path(),
+            "good.toml",
+            "name = \"good\"\nurl = \"http://good.local\"\n",
+        );
+        assert!(problems(&load_dirs(&dirs).await).is_empty());
+        write(dir.path(), "broken.toml", "name = \n");
+        write(
+            dir.path(),
+            "copy.toml",
+            "name = \"good\"\nurl = \"http://copy.local\"\n",
+        );
+        let problems = problems(&load_dirs(&dirs).await);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(
+            problems.iter().any(|p| p.contains("broken.toml")),
+            "{problems:?}"
+        );
+        assert!(
+            problems.iter().any(|p| p.contains("copy.toml")),
+            "{problems:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_first_file_to_define_a_name_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "b.toml",
+            "name = \"Grafana\"\nurl = \"http://b.local\"\n",
+        );
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"Grafana\"\nurl = \"http://a.local\"\n",
+        );
+        let cfg = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(names(&cfg), ["Grafana"]);
+        assert_eq!(cfg.services[0].url, "http://a.local");
+        let (path, e) = &cfg.errors[0];
+        assert!(path.ends_with("b.toml"));
+        assert!(e.contains("`Grafana` is already defined in"), "{e}");
+        assert!(e.contains("a.toml"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn poll_returns_once_there_is_a_change() {
+        let _cfg = test_support::lock_cfg().await;
+        let res = test_support::get("/poll").await;
+        let current: serde_json::Value =
+            serde_json::from_str(&test_support::text(res).await).unwrap();
+        let current = current["id"].as_u64().unwrap();
+        assert_eq!(current, watcher::version());
+        let uri = format!("/poll?since={current}");
+        let poll = tokio::spawn(async move { test_support::get(&uri).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!poll.is_finished());
+        watcher::reload(Vec::new()).await.unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(5), poll)
+            .await
+            .unwrap()
+            .unwrap();
+        let next: serde_json::Value = serde_json::from_str(&test_support::text(res).await).unwrap();
+        assert_eq!(next["id"].as_u64(), Some(current + 1));
+    }
+
+    #[test]
+    fn alt_urls_render_as_chips() {
+        let html = service(
+            r#"
+            name = "nas"
+            url = "http://nas.local"
+            alt_urls = ["http://100.64.0.2:5000/?a=1&b=2", "/nas"]
+            "#,
+        )
+        .as_html();
+        assert!(html.contains(r#"<div class="alt-urls">"#), "{html}");
+        assert!(
+            html.contains(
+                r#"<a class="alt-url" href="http://100.64.0.2:5000/?a=1&amp;b=2">100.64.0.2</a>"#
+            ),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<a class="alt-url" href="/nas">/nas</a>"#),
+            "{html}"
+        );
+        let without = service("name = \"a\"\nurl = \"http://a.local\"\n").as_html();
+        assert!(!without.contains("alt-urls"), "{without}");
+    }
+
+    #[tokio::test]
+    async fn invalid_alt_urls_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "a.toml",
+            "name = \"a\"\nurl = \"http://a.local\"\nalt_urls = [\"ftp://a\", \"http://b.local\"]\n",
+        );
+        let cfg = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(cfg.services[0].alt_urls, ["http://b.local"]);
+        assert_eq!(cfg.errors.len(), 1);
+    }
+
+    #[test]
+    fn pinned_services_lead_in_their_own_section() {
+        let html = services(
+            r#"
+            [[service]]
+            name = "a"
+            url = "http://a.local"
+            category = "Media"
+
+            [[service]]
+            name = "b"
+            url = "http://b.local"
+            category = "Media"
+            pinned = true
+            "#,
+        )
+        .as_html();
+        assert!(html.starts_with(r#"<li class="service-category"><details data-pinned="" open><summary><h3>Pinned</h3>"#), "{html}");
+        assert_eq!(linked(&html), ["b", "a"]);
+        assert_eq!(sections(&html), ["Media"]);
+    }
+
+    #[test]
+    fn entries_are_reachable_with_the_keyboard() {
+        let html = service(
+            "name = \"Grafana\"\nurl = \"http://grafana.local\"\ndesc = \"Dashboards & graphs\"\n",
+        )
+        .as_html();
+        assert!(
+            html.contains(r#"<a class="service-link" href="http://grafana.local" aria-label="Grafana, Dashboards &amp; graphs">Grafana</a>"#),
+            "{html}"
+        );
+        assert!(html.starts_with("<article"), "{html}");
+        assert!(!html.contains("<div onclick"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn warming_the_cfg_logs_the_service_count() {
+        let _cfg = test_support::lock_cfg().await;
+        test_support::cfg_dir();
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = subscriber(
+            false,
+            EnvFilter::new("home_services=info"),
+            BoxMakeWriter::new(move || writer.clone()),
+            None,
+        );
+        let cfg = {
+            let _default = tracing::subscriber::set_default(subscriber);
+            warm_cfg().await.unwrap()
+        };
+        assert!(names(&cfg).contains(&"plex"));
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logged.contains(&format!(
+                "loaded {} service(s), 0 cfg error(s)",
+                cfg.services.len()
+            )),
+            "{logged}"
+        );
+    }
+
+    #[tokio::test]
+    async fn extensionless_files_are_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "service",
+            r#"{ "name": "json", "url": "http://json.local" }"#,
+        );
+        write(
+            dir.path(),
+            "yaml-service",
+            "name: yaml\nurl: http://yaml.local\n",
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert!(services.errors.is_empty(), "{:?}", services.errors);
+        let mut found = names(&services);
+        found.sort();
+        assert_eq!(found, ["json", "yaml"]);
+    }
+
+    #[test]
+    fn known_extensions_are_not_detected() {
+        let json = r#"{ "name": "json", "url": "http://json.local" }"#;
+        let (format, _) = CfgFormat::parse_file(Path::new("a"), json).unwrap();
+        assert_eq!(format, CfgFormat::Json);
+        assert!(CfgFormat::parse_file(Path::new("a.toml"), json).is_err());
+        let err = CfgFormat::parse_file(Path::new("a"), "][").unwrap_err();
+        assert!(err.contains("as toml") && err.contains("as yaml"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn only_safe_colors_become_the_accent() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "a.toml",
+            "[[service]]\nname = \"hex\"\nurl = \"/hex\"\ncolor = \"#1e88e5\"\n\n\
+             [[service]]\nname = \"named\"\nurl = \"/named\"\ncolor = \"teal\"\n\n\
+             [[service]]\nname = \"injected\"\nurl = \"/injected\"\n\
+             color = \"red; background: url(http://evil.local)\"\n",
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        let html = |name: &str| {
+            services
+                .services
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap()
+                .as_html()
+        };
+        assert!(html("hex").contains(r#"style="--accent: #1e88e5""#));
+        assert!(html("named").contains(r#"style="--accent: teal""#));
+        let injected = html("injected");
+        assert!(!injected.contains("style="), "{injected}");
+        assert!(!injected.contains("evil.local"), "{injected}");
+    }
+
+    #[tokio::test]
+    async fn tags_narrow_the_list() {
+        let hosts = |html: &str| {
+            let mut hosts = linked(html);
+            hosts.sort();
+            hosts.into_iter().map(str::to_string).collect::<Vec<_>>()
+        };
+        let html = test_support::text(test_support::get("/?tag=Monitoring").await).await;
+        assert_eq!(hosts(&html), ["grafana", "prometheus"]);
+        assert!(
+            html.contains(
+                r#"Showing services tagged <code>Monitoring</code> <a href="/">clear filter</a>"#
+            ),
+            "{html}"
+        );
+        let html = test_support::text(
+            test_support::get("/fragment/services?tag=monitoring&tag=internal").await,
+        )
+        .await;
+        assert_eq!(hosts(&html), ["grafana"]);
+        let html = test_support::text(test_support::get("/?tag=media&tag=internal").await).await;
+        assert!(hosts(&html).is_empty(), "{html}");
+        assert!(
+            html.contains("No services have every one of these tags"),
+            "{html}"
+        );
+        assert!(html.contains("clear filter"), "{html}");
+    }
+
+    async fn with_cookie(uri: &str, cookie: &str) -> Response {
+        let req = Request::get(uri)
+            .header(header::COOKIE, cookie)
+            .header(header::REFERER, "http://dash.local/?tag=media")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        test_support::send(req).await
+    }
+
+    #[tokio::test]
+    async fn collapsed_categories_render_closed() {
+        let html = test_support::text(with_cookie("/", "collapsed=c=Media").await).await;
+        assert!(
+            html.contains(r#"<details data-category="Media"><summary>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<details data-category="Monitoring" open><summary>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<details data-pinned="" open><summary>"#),
+            "{html}"
+        );
+        let html = test_support::text(with_cookie("/", "collapsed=p=1").await).await;
+        assert!(
+            html.contains(r#"<details data-pinned=""><summary>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<details data-category="Media" open>"#),
+            "{html}"
+        );
+    }
+
+    #[tokio::test]
+    async fn toggling_a_category_flips_its_cookie() {
+        let res = with_cookie("/toggle-category?name=Media", "collapsed=c=Monitoring").await;
+        assert!(res.status().is_redirection());
+        assert_eq!(res.headers()[header::LOCATION], "/?tag=media");
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(
+            cookie.starts_with("collapsed=c=Media&c=Monitoring;"),
+            "{cookie}"
+        );
+        let res = with_cookie("/toggle-category?name=Media", "collapsed=c=Media").await;
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("collapsed=;"), "{cookie}");
+        let res = with_cookie("/toggle-category?pinned=true&open=false", "").await;
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("collapsed=p=1;"), "{cookie}");
+    }
+
+    #[tokio::test]
+    async fn head_requests_get_the_get_headers_without_a_body() {
+        let _lock = test_support::lock_cfg().await;
+        for uri in ["/", "/index.html", "/api/services"] {
+            let get = test_support::get(uri).await;
+            let head =
+                test_support::send(Request::head(uri).body(axum::body::Body::empty()).unwrap())
+                    .await;
+            assert_eq!(head.status(), StatusCode::OK, "{uri}");
+            assert_eq!(
+                head.headers()[header::CONTENT_TYPE],
+                get.headers()[header::CONTENT_TYPE],
+                "{uri}"
+            );
+            let len = test_support::bytes(get).await.len();
+            assert!(len > 0, "{uri}");
+            assert_eq!(
+                head.headers()[header::CONTENT_LENGTH],
+                len.to_string().as_str(),
+                "{uri}"
+            );
+            assert!(test_support::bytes(head).await.is_empty(), "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reloading_rereads_the_cfg_and_stamps_the_footer() {
+        let _lock = test_support::lock_cfg().await;
+        let path = test_support::cfg_dir().join("zz-reloaded.toml");
+        std::fs::write(
+            &path,
+            "name = \"zz reloaded\"\nurl = \"http://reloaded.local\"\n",
+        )
+        .unwrap();
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let req = Request::post("/reload")
+            .header(header::REFERER, "http://dash.local/?tag=media")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = test_support::send(req).await;
+        assert!(res.status().is_redirection());
+        assert_eq!(res.headers()[header::LOCATION], "/?tag=media");
+        let reloaded = LAST_RELOADED
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unwrap();
+        assert!(reloaded >= before);
+
+        let html = test_support::text(test_support::get("/").await).await;
+        let at = last_reloaded().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(html.contains("http://reloaded.local"), "{html}");
+        assert!(
+            html.contains(&format!(
+                r#"<time id="last-reloaded" datetime="{at}">{at}</time>"#
+            )),
+            "{html}"
+        );
+    }
+
+    #[tokio::test]
+    async fn profiles_override_base_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "base/services.toml",
+            "[[service]]\nname = \"grafana\"\nurl = \"http://base.local/grafana\"\n\n\
+             [[service]]\nname = \"plex\"\nurl = \"http://plex.local\"\n",
+        );
+        write(
+            dir.path(),
+            "home/grafana.toml",
+            "name = \"grafana\"\nurl = \"http://home.local/grafana\"\n",
+        );
+        write(
+            dir.path(),
+            "work/jira.toml",
+            "name = \"jira\"\nurl = \"http://jira.local\"\n",
+        );
+        let dirs = apply_profile(vec![dir.path().to_path_buf()], Some("home"));
+        assert_eq!(dirs, [dir.path().join("base"), dir.path().join("home")]);
+        let cfg = load_dirs(&dirs).await;
+        let mut found = names(&cfg);
+        found.sort();
+        assert_eq!(found, ["grafana", "plex"]);
+        let grafana = cfg.services.iter().find(|s| s.name == "grafana").unwrap();
+        assert_eq!(grafana.url, "http://home.local/grafana");
+    }
+
+    #[test]
+    fn no_profile_or_an_unsafe_one_keeps_the_cfg_dirs() {
+        let dirs = vec![PathBuf::from("cfg")];
+        assert_eq!(apply_profile(dirs.clone(), None), dirs);
+        assert_eq!(apply_profile(dirs.clone(), Some(" ")), dirs);
+        assert_eq!(apply_profile(dirs.clone(), Some("../etc")), dirs);
+        assert_eq!(apply_profile(dirs.clone(), Some("a/b")), dirs);
+    }
+
+    #[tokio::test]
+    async fn reads_a_thousand_generated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..1000 {
+            write(
+                dir.path(),
+                &format!("generated/{}/svc-{i:04}.toml", i % 10),
+                &format!("name = \"svc-{i:04}\"\nurl = \"http://svc-{i:04}.local\"\n"),
+            );
+        }
+        let dirs = [dir.path().to_path_buf()];
+        let listings = list_cfg_dirs(&dirs).await.unwrap();
+        assert_eq!(listings.iter().map(|l| l.files.len()).sum::<usize>(), 1000);
+        let cfg = read_listings(&listings).await;
+        assert!(cfg.errors.is_empty(), "{:?}", cfg.errors);
+        assert_eq!(cfg.services.len(), 1000);
+        assert_eq!(linked(&cfg.as_html()).len(), 1000);
+    }
+
+    #[test]
+    fn services_can_be_grouped_by_host() {
+        let mut cfg = services(
+            r#"
+            [[service]]
+            name = "sonarr"
+            url = "http://nas.local:8989"
+            category = "Media"
+
+            [[service]]
+            name = "radarr"
+            url = "http://nas.local:7878"
+
+            [[service]]
+            name = "pihole"
+            url = "http://pi.local/admin"
+
+            [[service]]
+            name = "docs"
+            url = "/docs"
+            "#,
+        );
+        cfg.dashboard = toml::from_str(r#"group_by = "host""#).unwrap();
+        assert_eq!(cfg.dashboard.group_by, GroupBy::Host);
+        let html = cfg.as_html();
+        assert_eq!(sections(&html), ["Local", "nas.local", "pi.local"]);
+        let nas = html.split(r#"data-category="nas.local""#).nth(1).unwrap();
+        let nas = nas.split("</details>").next().unwrap();
+        assert!(nas.contains("sonarr") && nas.contains("radarr"), "{nas}");
+        assert!(!nas.contains("pihole"), "{nas}");
+    }
+
+    async fn unmatched_with(fallback: Fallback, method: Method) -> Response {
+        test_support::cfg_dir();
+        let req = Request::builder()
+            .method(method)
+            .uri("/app/settings?tab=1")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        respond_unmatched(&fallback, req).await
+    }
+
+    #[tokio::test]
+    async fn each_fallback_mode_answers_unknown_paths() {
+        let res = unmatched_with(Fallback::NotFound, Method::GET).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(test_support::text(res)
+            .await
+            .contains("<code>/app/settings</code>"));
+
+        let res = unmatched_with(Fallback::Index, Method::GET).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(test_support::text(res).await.contains("http://plex.local"));
+
+        let res = unmatched_with(Fallback::Redirect("/".to_string()), Method::GET).await;
+        assert!(res.status().is_redirection());
+        assert_eq!(res.headers()[header::LOCATION], "/");
+
+        for fallback in [Fallback::Index, Fallback::Redirect("/".to_string())] {
+            let res = unmatched_with(fallback, Method::POST).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn fallbacks_are_parsed() {
+        assert_eq!(parse_fallback("notfound"), Some(Fallback::NotFound));
+        assert_eq!(parse_fallback("index"), Some(Fallback::Index));
+        assert_eq!(
+            parse_fallback("redirect:/home"),
+            Some(Fallback::Redirect("/home".to_string()))
+        );
+        assert_eq!(parse_fallback("redirect://evil.local"), None);
+        assert_eq!(parse_fallback("redirect:home"), None);
+        assert_eq!(parse_fallback("spa"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_cfg_reads_give_up_with_an_error_page() {
+        let started = tokio::time::Instant::now();
+        let hung = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        };
+        let e = read_within(Duration::from_secs(5), hung).await.unwrap_err();
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+        assert!(
+            e.starts_with("Timed out after 5s reading the cfg dirs"),
+            "{e}"
+        );
+        let (status, Html(body)) = err(e, "reading cfg");
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("unresponsive mount"), "{body}");
+
+        let quick = read_within(Duration::from_secs(5), async { Ok(1) }).await;
+        assert_eq!(quick, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn oversized_and_binary_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "ok.toml",
+            "name = \"ok\"\nurl = \"http://ok.local\"\n",
+        );
+        std::fs::write(dir.path().join("binary.toml"), [0xff, 0xfe, 0x00, 0x9f]).unwrap();
+        let padding = "#".repeat(*MAX_CFG_FILE_BYTES as usize);
+        write(
+            dir.path(),
+            "huge.toml",
+            &format!("name = \"huge\"\nurl = \"http://huge.local\"\n{padding}\n"),
+        );
+        let services = load_dirs(&[dir.path().to_path_buf()]).await;
+        assert_eq!(names(&services), ["ok"]);
+        let mut errors: Vec<(String, &str)> = services
+            .errors
+            .iter()
+            .map(|(path, e)| {
+                (
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    e.as_str(),
+                )
+            })
+            .collect();
+        errors.sort();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(errors[0].0, "binary.toml");
+        assert!(
+            errors[0].1.starts_with("File isn't valid utf-8"),
+            "{errors:?}"
+        );
+        assert_eq!(errors[1].0, "huge.toml");
+        assert!(errors[1].1.contains("byte limit"), "{errors:?}");
+    }
+
+    #[tokio::test]
+    async fn files_are_read_up_to_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.toml");
+        std::fs::write(&path, "12345").unwrap();
+        assert_eq!(read_capped_text(&path, 5).await.unwrap(), "12345");
+        assert!(read_capped_text(&path, 4).await.is_err());
     }
 }
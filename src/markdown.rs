@@ -0,0 +1,158 @@
+use pulldown_cmark::{CowStr, Event, Parser, Tag};
+
+use crate::escape_html;
+
+/// Render `src` as markdown. Any raw html in the source is escaped rather
+/// than passed through and link or image destinations that aren't http(s),
+/// mailto or relative are replaced with `#`.
+pub fn render(src: &str) -> String {
+    let events = Parser::new(src).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut ret = String::with_capacity(src.len());
+    pulldown_cmark::html::push_html(&mut ret, events);
+    ret
+}
+
+fn safe_url(url: CowStr) -> CowStr {
+    let lower = url.trim().to_ascii_lowercase();
+    let is_relative = lower.starts_with('/') || lower.starts_with('#') || !lower.contains(':');
+    let is_safe_scheme = ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme));
+    if is_relative || is_safe_scheme {
+        url
+    } else {
+        tracing::debug!("dropping unsafe markdown url `{url}`");
+        CowStr::Borrowed("#")
+    }
+}
+
+/// Escape `src` as plain text with any bare http(s) urls wrapped in links.
+///
+/// Urls are found in the raw text and every piece is escaped on its own, so
+/// the escaped text is never scanned again and nothing is escaped twice.
+pub fn linkify(src: &str) -> String {
+    let lower = src.to_ascii_lowercase();
+    let mut ret = String::with_capacity(src.len());
+    let mut pos = 0;
+    while let Some((start, end)) = next_url(src, &lower, pos) {
+        ret.push_str(&escape_html(&src[pos..start]));
+        let url = escape_html(&src[start..end]);
+        ret.push_str(&format!(r#"<a href="{url}" rel="noopener">{url}</a>"#));
+        pos = end;
+    }
+    ret.push_str(&escape_html(&src[pos..]));
+    ret
+}
+
+/// The byte range of the first url at or after `from`, `lower` is `src`
+/// lowercased so the offsets are shared
+fn next_url(src: &str, lower: &str, mut from: usize) -> Option<(usize, usize)> {
+    loop {
+        let start = from
+            + ["http://", "https://"]
+                .iter()
+                .filter_map(|scheme| lower[from..].find(scheme))
+                .min()?;
+        let scheme_len = if lower[start..].starts_with("https://") {
+            8
+        } else {
+            7
+        };
+        // part of a longer word like `xhttp://`
+        let in_word = src[..start]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric);
+        let rest = &src[start..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '\'' | '`'))
+            .unwrap_or(rest.len());
+        // trailing punctuation is more likely to end the sentence than the url
+        let url = rest[..len].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']);
+        if !in_word && url.len() > scheme_len {
+            return Some((start, start + url.len()));
+        }
+        from = start + scheme_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_bold_and_links() {
+        let html = render("**bold** [link](http://x)");
+        assert!(html.contains("<strong>bold</strong>"), "{html}");
+        assert!(html.contains(r#"<a href="http://x">link</a>"#), "{html}");
+    }
+
+    #[test]
+    fn escapes_raw_html_and_drops_unsafe_urls() {
+        let html = render("<script>alert(1)</script>\n\n[x](javascript:alert(1))");
+        assert!(!html.contains("<script>"), "{html}");
+        assert!(html.contains("&lt;script&gt;"), "{html}");
+        assert!(html.contains(r##"<a href="#">x</a>"##), "{html}");
+    }
+
+    #[test]
+    fn only_markdown_services_are_rendered() {
+        let plain: crate::Service =
+            toml::from_str("name = \"a\"\nurl = \"http://a.local\"\ndesc = \"**a**\"\n").unwrap();
+        let markdown: crate::Service = toml::from_str(
+            "name = \"a\"\nurl = \"http://a.local\"\ndesc = \"**a**\"\nmarkdown = true\n",
+        )
+        .unwrap();
+        assert!(plain.as_html().contains("**a**"));
+        assert!(markdown.as_html().contains("<strong>a</strong>"));
+    }
+
+    #[test]
+    fn bare_urls_become_exactly_one_link() {
+        let html = linkify("Docs at https://example.com/a?b=1&c=2. <b>not</b> markup");
+        assert_eq!(
+            html,
+            "Docs at <a href=\"https://example.com/a?b=1&amp;c=2\" rel=\"noopener\">\
+             https://example.com/a?b=1&amp;c=2</a>. &lt;b&gt;not&lt;/b&gt; markup"
+        );
+        assert_eq!(html.matches("<a ").count(), 1);
+        assert!(!html.contains("&amp;amp;"), "{html}");
+    }
+
+    #[test]
+    fn urls_cannot_break_out_of_the_attribute() {
+        let html = linkify(r#"http://x.local/"onmouseover="alert(1) and xhttp://y.local"#);
+        assert_eq!(html.matches("<a ").count(), 1, "{html}");
+        assert!(
+            html.starts_with(r#"<a href="http://x.local/" rel="noopener">"#),
+            "{html}"
+        );
+        assert!(html.contains("&quot;onmouseover=&quot;"), "{html}");
+        assert_eq!(linkify("http:// alone"), "http:// alone");
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default)]
+struct Metrics {
+    /// request counts keyed by method and status code
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    request_duration: Mutex<Histogram>,
+    configured: AtomicUsize,
+    /// how long each cfg load that re-parsed the files took, cache hits aren't counted
+    cfg_read_duration: Mutex<Histogram>,
+}
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DURATION_BUCKETS)
+    }
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.buckets.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bound, count) in self.buckets.iter().zip(self.counts.iter()) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+/// Record the number of enabled services found by the latest cfg load
+pub fn set_configured(count: usize) {
+    METRICS.configured.store(count, Ordering::Relaxed);
+}
+
+/// Record how long a cfg load that re-parsed the files took
+pub fn record_cfg_read(elapsed: Duration) {
+    METRICS
+        .cfg_read_duration
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .observe(elapsed.as_secs_f64());
+}
+
+fn record_request(method: &str, status: u16, elapsed: Duration) {
+    *METRICS
+        .requests
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry((method.to_string(), status))
+        .or_default() += 1;
+    METRICS
+        .request_duration
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Middleware counting every request and how long it took to produce a response
+pub async fn track(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let res = next.run(req).await;
+    record_request(&method, res.status().as_u16(), start.elapsed());
+    res
+}
+
+pub async fn render() -> impl IntoResponse {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP home_services_http_requests_total Number of http requests handled"
+    );
+    let _ = writeln!(out, "# TYPE home_services_http_requests_total counter");
+    for ((method, status), count) in METRICS
+        .requests
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+    {
+        let _ = writeln!(
+            out,
+            "home_services_http_requests_total{{method=\"{method}\",status=\"{status}\"}} {count}"
+        );
+    }
+    METRICS
+        .request_duration
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .render(
+            "home_services_http_request_duration_seconds",
+            "Time taken to produce a response",
+            &mut out,
+        );
+    let _ = writeln!(
+        out,
+        "# HELP home_services_configured_total Number of enabled services currently configured"
+    );
+    let _ = writeln!(out, "# TYPE home_services_configured_total gauge");
+    let _ = writeln!(
+        out,
+        "home_services_configured_total {}",
+        METRICS.configured.load(Ordering::Relaxed)
+    );
+    METRICS
+        .cfg_read_duration
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .render(
+            "home_services_cfg_read_duration_seconds",
+            "Time taken to read the cfg dirs",
+            &mut out,
+        );
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn metrics_lists_requests_and_services() {
+        assert_eq!(test_support::get("/").await.status(), StatusCode::OK);
+        let res = test_support::get("/metrics").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let text = test_support::text(res).await;
+        for name in [
+            "# TYPE home_services_http_requests_total counter",
+            "home_services_http_requests_total{method=\"GET\",status=\"200\"}",
+            "# TYPE home_services_http_request_duration_seconds histogram",
+            "home_services_http_request_duration_seconds_bucket{le=\"+Inf\"}",
+            "# TYPE home_services_configured_total gauge",
+        ] {
+            assert!(text.contains(name), "missing {name} in {text}");
+        }
+    }
+
+    fn read_count(text: &str) -> u64 {
+        text.lines()
+            .find_map(|line| line.strip_prefix("home_services_cfg_read_duration_seconds_count "))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_service_gauge_follows_each_load() {
+        let _cfg = test_support::lock_cfg().await;
+        test_support::cfg_dir();
+        let count = crate::read_cached_cfg()
+            .await
+            .unwrap()
+            .services
+            .services
+            .iter()
+            .filter(|s| s.enabled)
+            .count();
+        // the fixture's `hidden` service is disabled
+        assert_eq!(count, 4);
+        let text = test_support::text(test_support::get("/metrics").await).await;
+        assert!(
+            text.contains(&format!("\nhome_services_configured_total {count}\n")),
+            "{text}"
+        );
+        assert!(
+            text.contains("# TYPE home_services_cfg_read_duration_seconds histogram"),
+            "{text}"
+        );
+        assert!(read_count(&text) > 0, "{text}");
+
+        // nothing changed so this is a cache hit, which isn't timed
+        crate::read_cached_cfg().await.unwrap();
+        let again = test_support::text(test_support::get("/metrics").await).await;
+        assert_eq!(read_count(&again), read_count(&text));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub const ENV_VAR_RATE_LIMIT: &str = "HOME_SERVICE_RATE_LIMIT";
+/// Requests per second for each client, enough for a page load and its
+/// assets several times over
+const DEFAULT_RATE: f64 = 20.0;
+/// Paths that are never limited, sse connections are long lived and reconnect
+/// on their own and browsers ask for the favicon on every page load
+const EXEMPT: &[&str] = &["/sse", "/favicon.ico"];
+/// Static files a single page load can request dozens of, none of them read the cfg
+const EXEMPT_PREFIXES: &[&str] = &["/assets/", "/favicons/"];
+/// Once this many clients are tracked the idle ones are forgotten
+const MAX_CLIENTS: usize = 1024;
+const IDLE_AFTER: Duration = Duration::from_secs(60);
+
+static LIMITER: LazyLock<Option<Limiter>> = LazyLock::new(Limiter::from_env);
+
+struct Limiter {
+    rate: f64,
+    /// Twice the rate so a quick burst of refreshes is still allowed
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    /// `None` when limiting has been disabled by setting the rate to 0
+    fn from_env() -> Option<Self> {
+        let rate = match std::env::var(ENV_VAR_RATE_LIMIT) {
+            Err(_) => DEFAULT_RATE,
+            Ok(value) => match value.trim().parse::<f64>() {
+                Ok(0.0) => {
+                    tracing::info!("{ENV_VAR_RATE_LIMIT} is 0, not rate limiting requests");
+                    return None;
+                }
+                Ok(rate) if rate.is_finite() && rate > 0.0 => rate,
+                _ => {
+                    tracing::warn!(
+                        "invalid {ENV_VAR_RATE_LIMIT} `{value}`, using {DEFAULT_RATE} per second"
+                    );
+                    DEFAULT_RATE
+                }
+            },
+        };
+        Some(Self::new(rate))
+    }
+
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            burst: rate * 2.0,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Take a token from `ip`'s bucket, `false` if it's empty
+    fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= MAX_CLIENTS {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < IDLE_AFTER);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Static files and proxied services are left alone, a proxied app makes its
+/// own requests at whatever rate it needs
+fn is_exempt(path: &str) -> bool {
+    EXEMPT.contains(&path)
+        || EXEMPT_PREFIXES
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+        || crate::proxy::is_proxied(path)
+}
+
+/// Middleware answering with a 429 once a client has used up its requests,
+/// requests without a peer address, like over a unix socket, aren't limited
+pub async fn limit(req: Request, next: Next) -> Response {
+    match LIMITER.as_ref() {
+        Some(limiter) => throttle(limiter, req, next).await,
+        None => next.run(req).await,
+    }
+}
+
+async fn throttle(limiter: &Limiter, req: Request, next: Next) -> Response {
+    if is_exempt(req.uri().path()) {
+        return next.run(req).await;
+    }
+    let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return next.run(req).await;
+    };
+    if limiter.allow(addr.ip()) {
+        return next.run(req).await;
+    }
+    tracing::debug!("rate limiting {}", addr.ip());
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, "1")],
+        "too many requests",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// One request per second, so only a burst of two gets through
+    fn app() -> Router {
+        let limiter = Arc::new(Limiter::new(1.0));
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route("/sse", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(move |req, next| {
+                let limiter = limiter.clone();
+                async move { throttle(&limiter, req, next).await }
+            }))
+    }
+
+    fn from(uri: &str, ip: [u8; 4]) -> Request {
+        let mut req = Request::get(uri).body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 50000))));
+        req
+    }
+
+    async fn statuses(app: &Router, uri: &str, ip: [u8; 4], count: usize) -> Vec<StatusCode> {
+        let mut statuses = Vec::new();
+        for _ in 0..count {
+            let res = app.clone().oneshot(from(uri, ip)).await.unwrap();
+            statuses.push(res.status());
+        }
+        statuses
+    }
+
+    #[tokio::test]
+    async fn requests_past_the_burst_get_a_429() {
+        let app = app();
+        assert_eq!(
+            statuses(&app, "/", [10, 0, 0, 1], 3).await,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS
+            ]
+        );
+        let res = app.clone().oneshot(from("/", [10, 0, 0, 1])).await.unwrap();
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+        // each client has its own bucket
+        assert_eq!(
+            statuses(&app, "/", [10, 0, 0, 2], 1).await,
+            [StatusCode::OK]
+        );
+    }
+
+    #[tokio::test]
+    async fn sse_and_unix_socket_requests_are_never_limited() {
+        let app = app();
+        let sse = statuses(&app, "/sse", [10, 0, 0, 1], 5).await;
+        assert!(sse.iter().all(|s| *s == StatusCode::OK), "{sse:?}");
+        for _ in 0..5 {
+            let req = Request::get("/").body(Body::empty()).unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[test]
+    fn static_files_are_exempt() {
+        assert!(is_exempt("/sse"));
+        assert!(is_exempt("/favicon.ico"));
+        assert!(is_exempt("/assets/main.css"));
+        assert!(is_exempt("/favicons/0123"));
+        assert!(!is_exempt("/"));
+        assert!(!is_exempt("/api/services"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// Incoming ids longer than this are replaced rather than logged
+const MAX_LEN: usize = 128;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A random looking 16 digit hex id, unique for the life of the process
+fn generate() -> HeaderValue {
+    let hash = RandomState::new().hash_one(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    HeaderValue::from_str(&format!("{hash:016x}")).expect("hex is a valid header value")
+}
+
+/// Middleware keeping the client's `X-Request-Id` or assigning a new one,
+/// the id is set on the request before it is traced and echoed in the response
+pub async fn propagate(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_LEN && v.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(generate);
+    req.headers_mut().insert(X_REQUEST_ID.clone(), id.clone());
+    let mut res = next.run(req).await;
+    res.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    res
+}
+
+/// For `TraceLayer::make_span_with`, runs after [`propagate`] so the id is
+/// always present
+pub fn make_span(req: &Request) -> Span {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        path = req.uri().path(),
+        request_id = id,
+    )
+}
+
+/// For `TraceLayer::on_response`
+pub fn on_response(res: &Response, latency: Duration, _span: &Span) {
+    tracing::debug!(
+        status = res.status().as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        "finished processing request"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn responses_carry_a_request_id() {
+        let first = test_support::get("/health").await;
+        let second = test_support::get("/health").await;
+        let first = first.headers()[&X_REQUEST_ID].to_str().unwrap().to_string();
+        assert_eq!(first.len(), 16, "{first}");
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()), "{first}");
+        assert_ne!(first, second.headers()[&X_REQUEST_ID]);
+    }
+
+    #[tokio::test]
+    async fn incoming_request_ids_are_echoed() {
+        let req = Request::get("/health")
+            .header(&X_REQUEST_ID, "from-the-proxy")
+            .body(Body::empty())
+            .unwrap();
+        let res = test_support::send(req).await;
+        assert_eq!(res.headers()[&X_REQUEST_ID], "from-the-proxy");
+        let too_long = "x".repeat(MAX_LEN + 1);
+        let req = Request::get("/health")
+            .header(&X_REQUEST_ID, too_long.as_str())
+            .body(Body::empty())
+            .unwrap();
+        let res = test_support::send(req).await;
+        assert_ne!(res.headers()[&X_REQUEST_ID], too_long.as_str());
+    }
+}
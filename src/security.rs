@@ -0,0 +1,93 @@
+use std::sync::LazyLock;
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+pub const ENV_VAR_CSP: &str = "HOME_SERVICE_CSP";
+/// The dashboard relies on inline `onclick` handlers, an inline script and
+/// inline styles while service icons can be hosted anywhere
+const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; \
+    style-src 'self' 'unsafe-inline'; img-src * data:; connect-src 'self'; \
+    frame-ancestors 'none'; base-uri 'self'; form-action 'self'";
+
+static CSP: LazyLock<Option<HeaderValue>> = LazyLock::new(csp_from_env);
+
+/// `None` when the policy has been disabled by setting it to an empty string
+fn csp_from_env() -> Option<HeaderValue> {
+    let Ok(value) = std::env::var(ENV_VAR_CSP) else {
+        return Some(HeaderValue::from_static(DEFAULT_CSP));
+    };
+    if value.trim().is_empty() {
+        tracing::info!("{ENV_VAR_CSP} is empty, not sending a content security policy");
+        return None;
+    }
+    match HeaderValue::from_str(value.trim()) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!("invalid {ENV_VAR_CSP} `{value}`, using the default: {e}");
+            Some(HeaderValue::from_static(DEFAULT_CSP))
+        }
+    }
+}
+
+/// Middleware adding the usual defensive headers to html responses, proxied
+/// responses keep whatever the upstream sent
+pub async fn headers(req: Request, next: Next) -> Response {
+    let mut res = next.run(req).await;
+    if res.extensions().get::<crate::proxy::Proxied>().is_some() {
+        return res;
+    }
+    let is_html = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if !is_html {
+        return res;
+    }
+    let headers = res.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    // `/theme` redirects back using the referer so it has to be kept for
+    // same origin requests
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("same-origin"),
+    );
+    if let Some(csp) = CSP.as_ref() {
+        headers.insert(header::CONTENT_SECURITY_POLICY, csp.clone());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn html_responses_get_the_security_headers() {
+        let res = test_support::get("/").await;
+        let headers = res.headers();
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[header::REFERRER_POLICY], "same-origin");
+        let csp = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        // the inline `goto` handlers and the sse connection have to keep working
+        assert!(csp.contains("script-src 'self' 'unsafe-inline'"), "{csp}");
+        assert!(csp.contains("connect-src 'self'"), "{csp}");
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_left_alone() {
+        let res = test_support::get("/api/services").await;
+        assert!(res.headers().get(header::X_FRAME_OPTIONS).is_none());
+    }
+}
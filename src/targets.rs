@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use axum::{http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{read_cfg, Service};
+
+/// One entry in prometheus' file based service discovery format
+#[derive(Debug, Serialize)]
+pub struct TargetGroup {
+    targets: Vec<String>,
+    labels: BTreeMap<&'static str, String>,
+}
+
+impl TargetGroup {
+    /// `None` when the service's url has no host and port to scrape
+    fn from_service(service: &Service) -> Option<Self> {
+        let url = reqwest::Url::parse(&service.url).ok()?;
+        let host = url.host_str()?;
+        let port = url.port_or_known_default()?;
+        Some(Self {
+            targets: vec![format!("{host}:{port}")],
+            labels: BTreeMap::from([("name", service.name.clone())]),
+        })
+    }
+}
+
+pub async fn render() -> Result<Json<Vec<TargetGroup>>, (StatusCode, String)> {
+    let cfg = read_cfg()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let groups = cfg
+        .without_disabled()
+        .services
+        .iter()
+        .filter_map(TargetGroup::from_service)
+        .collect();
+    Ok(Json(groups))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn targets_use_prometheus_file_sd_format() {
+        let res = test_support::get("/targets.json").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let groups: serde_json::Value =
+            serde_json::from_str(&test_support::text(res).await).unwrap();
+        let groups = groups.as_array().unwrap();
+        for group in groups {
+            let group = group.as_object().unwrap();
+            assert_eq!(group.len(), 2, "{group:?}");
+            assert!(group["targets"].as_array().unwrap()[0].is_string());
+            assert!(group["labels"]["name"].is_string());
+        }
+        assert!(groups.contains(&serde_json::json!({
+            "targets": ["plex.local:80"],
+            "labels": { "name": "plex" },
+        })));
+        assert!(!groups.iter().any(|g| g["labels"]["name"] == "hidden"));
+    }
+
+    #[test]
+    fn services_without_a_host_are_skipped() {
+        let relative: Service = toml::from_str("name = \"a\"\nurl = \"/grafana\"").unwrap();
+        assert!(TargetGroup::from_service(&relative).is_none());
+        let https: Service = toml::from_str("name = \"b\"\nurl = \"https://b.local/x\"").unwrap();
+        assert_eq!(
+            TargetGroup::from_service(&https).unwrap().targets,
+            ["b.local:443"]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{borrow::Cow, path::Path, sync::LazyLock};
+
+pub const ENV_VAR_INDEX_TEMPLATE: &str = "HOME_SERVICE_INDEX_TEMPLATE";
+pub const ENV_VAR_ERROR_TEMPLATE: &str = "HOME_SERVICE_ERROR_TEMPLATE";
+static DEFAULT_INDEX: &str = include_str!("index.template.html");
+static DEFAULT_ERROR: &str = include_str!("error.template.html");
+
+static INDEX: LazyLock<Cow<'static, str>> =
+    LazyLock::new(|| from_env(ENV_VAR_INDEX_TEMPLATE, "{{services-list}}", DEFAULT_INDEX));
+static ERROR: LazyLock<Cow<'static, str>> =
+    LazyLock::new(|| from_env(ENV_VAR_ERROR_TEMPLATE, "{{e}}", DEFAULT_ERROR));
+
+/// Load any custom templates now so problems with them are logged at startup
+pub fn init_from_env() {
+    LazyLock::force(&INDEX);
+    LazyLock::force(&ERROR);
+}
+
+pub fn index() -> &'static str {
+    &INDEX
+}
+
+pub fn error() -> &'static str {
+    &ERROR
+}
+
+/// The file `env_var` points to, as long as it can be read and contains
+/// `required`, otherwise `default`
+fn from_env(env_var: &str, required: &str, default: &'static str) -> Cow<'static, str> {
+    match std::env::var_os(env_var) {
+        Some(path) => load(env_var, Path::new(&path), required, default),
+        None => Cow::Borrowed(default),
+    }
+}
+
+fn load(env_var: &str, path: &Path, required: &str, default: &'static str) -> Cow<'static, str> {
+    match std::fs::read_to_string(path) {
+        Ok(template) if template.contains(required) => {
+            tracing::info!("using the template `{}` from {env_var}", path.display());
+            Cow::Owned(template)
+        }
+        Ok(_) => {
+            tracing::warn!(
+                "{env_var} `{}` is missing `{required}`, using the default",
+                path.display()
+            );
+            Cow::Borrowed(default)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "failed to read {env_var} `{}`, using the default: {e}",
+                path.display()
+            );
+            Cow::Borrowed(default)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Services, Theme};
+
+    fn index_from(template: Option<&str>) -> Cow<'static, str> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        if let Some(template) = template {
+            std::fs::write(&path, template).unwrap();
+        }
+        load(
+            ENV_VAR_INDEX_TEMPLATE,
+            &path,
+            "{{services-list}}",
+            DEFAULT_INDEX,
+        )
+    }
+
+    #[test]
+    fn custom_index_templates_are_rendered() {
+        let template = index_from(Some(
+            "<html data-theme=\"{{theme}}\"><h1>my layout</h1><ul>{{services-list}}</ul></html>",
+        ));
+        let cfg: Services = toml::from_str("service = []").unwrap();
+        let html = crate::fill_index(&template, &cfg, Theme::Dark, "<li>plex</li>");
+        assert_eq!(
+            html,
+            "<html data-theme=\"dark\"><h1>my layout</h1><ul><li>plex</li></ul></html>"
+        );
+    }
+
+    #[test]
+    fn unusable_templates_fall_back_to_the_default() {
+        assert_eq!(index_from(Some("<h1>no list</h1>")), DEFAULT_INDEX);
+        assert_eq!(index_from(None), DEFAULT_INDEX);
+        let error = load(
+            ENV_VAR_ERROR_TEMPLATE,
+            Path::new("/nonexistent/error.html"),
+            "{{e}}",
+            DEFAULT_ERROR,
+        );
+        assert_eq!(error, DEFAULT_ERROR);
+    }
+}
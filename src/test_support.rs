@@ -0,0 +1,122 @@
+//! Helpers shared by the tests of several modules
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{Request, Response},
+    Router,
+};
+use http_body_util::BodyExt;
+use tokio::sync::{Mutex, MutexGuard};
+use tower::ServiceExt;
+
+/// The services every router test shares, they must only be read since the
+/// tests run at the same time
+const FIXTURE: &str = r#"
+[[service]]
+name = "plex"
+url = "http://plex.local"
+category = "Media"
+tags = ["media"]
+
+[[service]]
+name = "grafana"
+url = "http://grafana.local"
+category = "Monitoring"
+tags = ["monitoring", "internal"]
+
+[[service]]
+name = "prometheus"
+url = "http://prometheus.local"
+category = "Monitoring"
+tags = ["monitoring"]
+
+[[service]]
+name = "router"
+url = "http://router.local"
+pinned = true
+
+[[service]]
+name = "hidden"
+url = "http://hidden.local"
+enabled = false
+"#;
+
+static CFG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Point [`crate::CFG_PATH`] at a dir holding [`FIXTURE`], once for every test
+pub fn cfg_dir() -> &'static Path {
+    CFG_DIR.get_or_init(|| {
+        let dir = tempfile::tempdir().unwrap().into_path();
+        std::fs::write(dir.join("fixture.toml"), FIXTURE).unwrap();
+        crate::CFG_PATH.set(vec![dir.clone()]).unwrap();
+        dir
+    })
+}
+
+/// Held by tests that change the cfg or force a reload, and by the ones that
+/// need it to stay the same between two requests
+pub async fn lock_cfg() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::const_new(());
+    LOCK.lock().await
+}
+
+/// Send `req` to the app serving [`FIXTURE`]
+pub async fn send(req: Request<Body>) -> Response<Body> {
+    cfg_dir();
+    crate::app(Path::new("assets")).oneshot(req).await.unwrap()
+}
+
+pub async fn get(uri: &str) -> Response<Body> {
+    send(Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+pub async fn bytes(res: Response<Body>) -> Bytes {
+    res.into_body().collect().await.unwrap().to_bytes()
+}
+
+pub async fn text(res: Response<Body>) -> String {
+    String::from_utf8(bytes(res).await.to_vec()).unwrap()
+}
+
+/// Read the body of a streaming response like `/sse` until it contains
+/// every one of `needles`, failing the test if that takes too long
+pub async fn read_until(res: Response<Body>, needles: &[&str]) -> String {
+    let mut body = res.into_body();
+    let mut text = String::new();
+    let read = async {
+        while !needles.iter().all(|needle| text.contains(needle)) {
+            let Some(frame) = body.frame().await else {
+                break;
+            };
+            if let Ok(data) = frame.unwrap().into_data() {
+                text.push_str(std::str::from_utf8(&data).unwrap());
+            }
+        }
+    };
+    let timed_out = tokio::time::timeout(Duration::from_secs(5), read)
+        .await
+        .is_err();
+    assert!(!timed_out, "timed out waiting for {needles:?} in {text:?}");
+    text
+}
+
+/// Serve `app` on a random local port for the rest of the test
+pub async fn serve(app: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// A local address nothing is listening on
+pub async fn closed_addr() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
@@ -0,0 +1,92 @@
+use std::{
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+
+use axum::Router;
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::shutdown_signal;
+
+/// Removes the socket file when dropped, including when the grace period
+/// runs out before every connection has closed
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            tracing::warn!("failed to remove `{}`: {e}", self.0.display());
+        }
+    }
+}
+
+impl crate::Listener for UnixListener {
+    type Io = UnixStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Io, Option<SocketAddr>)> {
+        let (stream, _) = UnixListener::accept(self).await?;
+        Ok((stream, None))
+    }
+}
+
+/// Serve `app` over a unix socket at `path` until a shutdown signal is received,
+/// a socket left behind by an earlier run is replaced
+pub async fn serve(path: &Path, app: Router) -> Result<(), String> {
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        tracing::debug!("removing stale socket `{}`", path.display());
+        let _ = std::fs::remove_file(path);
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("failed to listen on `{}`: {e}", path.display()))?;
+    let _socket_file = SocketFile(path.to_path_buf());
+    tracing::debug!("listening on `{}`", path.display());
+    crate::serve_listener(listener, app, shutdown_signal()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn serves_over_the_socket_replacing_a_stale_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("home-services.sock");
+        // left behind like after a crash
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        test_support::cfg_dir();
+        let app = crate::app(Path::new("assets"));
+        let socket = path.clone();
+        tokio::spawn(async move { serve(&socket, app).await.unwrap() });
+
+        let mut stream = loop {
+            match tokio::net::UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK"), "{res}");
+        assert!(res.contains("http://plex.local"), "{res}");
+    }
+
+    #[tokio::test]
+    async fn a_missing_dir_is_an_error_naming_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("home-services.sock");
+        test_support::cfg_dir();
+        let e = serve(&path, crate::app(Path::new("assets")))
+            .await
+            .unwrap_err();
+        assert!(e.contains(&path.display().to_string()), "{e}");
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock,
+    },
+    time::Duration,
+};
+
+use futures::{Stream, StreamExt};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::Serialize;
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
+
+use crate::{invalidate_cfg_cache, read_cfg_html};
+
+pub const ENV_VAR_DEBOUNCE: &str = "HOME_SERVICE_SSE_DEBOUNCE_MS";
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(250);
+const UPDATES_CAPACITY: usize = 16;
+const REWATCH_DELAY: Duration = Duration::from_secs(5);
+
+type WatchReceiver = mpsc::UnboundedReceiver<notify::Result<notify::Event>>;
+
+static UPDATES: LazyLock<broadcast::Sender<CfgUpdate>> =
+    LazyLock::new(|| broadcast::channel(UPDATES_CAPACITY).0);
+/// The id of the latest [`CfgUpdate`], 0 until the first change
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// Sent to every subscriber once a debounced batch of changes has been reloaded
+#[derive(Debug, Clone)]
+pub struct CfgUpdate {
+    /// Increases by 1 with every update
+    pub version: u64,
+    pub changes: Vec<Change>,
+    /// The freshly rendered services list, `None` if reloading the cfg failed
+    pub services_html: Option<Arc<str>>,
+}
+
+pub fn subscribe() -> broadcast::Receiver<CfgUpdate> {
+    UPDATES.subscribe()
+}
+
+pub fn version() -> u64 {
+    VERSION.load(Ordering::Relaxed)
+}
+
+/// Watch the cfg dirs for the life of the process, reloading the cfg and
+/// broadcasting a [`CfgUpdate`] for every debounced batch of changes.
+pub async fn run(cfg_paths: &'static [PathBuf]) {
+    for cfg_path in cfg_paths {
+        if let Err(e) = tokio::fs::create_dir_all(cfg_path).await {
+            tracing::error!("failed to create cfg dir `{}`: {e}", cfg_path.display());
+        }
+    }
+    let stream = match watch(cfg_paths) {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::error!(
+                "error setting up watcher for cfg paths {cfg_paths:?}, live updates are disabled: {e}"
+            );
+            return;
+        }
+    };
+    let mut batches = std::pin::pin!(debounce(stream, debounce_from_env()));
+    while let Some(batch) = batches.next().await {
+        if let Err(e) = reload(changes(cfg_paths, &batch)).await {
+            tracing::warn!("failed to reload cfg after change: {e}");
+        }
+    }
+}
+
+/// Re-read the cfg, ignoring the cache, and broadcast a [`CfgUpdate`] for
+/// `changes` whether or not that succeeded
+pub async fn reload(changes: Vec<Change>) -> Result<(), String> {
+    invalidate_cfg_cache();
+    let services_html = read_cfg_html().await;
+    let version = VERSION.fetch_add(1, Ordering::Relaxed) + 1;
+    // an error here only means nobody is currently subscribed
+    let _ = UPDATES.send(CfgUpdate {
+        version,
+        changes,
+        services_html: services_html.as_ref().ok().cloned(),
+    });
+    services_html.map(drop)
+}
+
+pub fn debounce_from_env() -> Duration {
+    let Ok(value) = std::env::var(ENV_VAR_DEBOUNCE) else {
+        return DEFAULT_DEBOUNCE;
+    };
+    value
+        .trim()
+        .parse()
+        .map(Duration::from_millis)
+        .unwrap_or_else(|e| {
+            tracing::warn!("invalid {ENV_VAR_DEBOUNCE} `{value}`: {e}");
+            DEFAULT_DEBOUNCE
+        })
+}
+
+/// Watch every one of `paths` and everything below them, yielding an item for every create,
+/// modify or remove event. The underlying watcher lives as long as the returned stream.
+///
+/// Errors from the watcher are logged and the watch is re-established, after
+/// which an [`EventKind::Any`] event is yielded so anything that changed while
+/// the watch was down is still picked up.
+pub fn watch(paths: &[PathBuf]) -> notify::Result<impl Stream<Item = notify::Event>> {
+    let (watcher, rx) = start_watch(paths)?;
+    Ok(events(paths.to_vec(), watcher, rx))
+}
+
+/// The cfg changes received on `rx`, replacing `watcher` whenever it reports an error
+fn events(
+    paths: Vec<PathBuf>,
+    watcher: RecommendedWatcher,
+    rx: WatchReceiver,
+) -> impl Stream<Item = notify::Event> {
+    futures::stream::unfold(
+        (paths, watcher, rx),
+        |(paths, mut watcher, mut rx)| async move {
+            loop {
+                let e = match rx.recv().await {
+                    Some(Ok(event)) if is_cfg_change(&event.kind) => {
+                        return Some((event, (paths, watcher, rx)))
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => e.to_string(),
+                    None => "event channel closed".to_string(),
+                };
+                tracing::warn!("Error from cfg watcher, re-establishing the watch: {e}");
+                (watcher, rx) = rewatch(&paths).await;
+                return Some((notify::Event::new(EventKind::Any), (paths, watcher, rx)));
+            }
+        },
+    )
+}
+
+fn start_watch(paths: &[PathBuf]) -> notify::Result<(RecommendedWatcher, WatchReceiver)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = RecommendedWatcher::new(
+        move |res: notify::Result<notify::Event>| {
+            // the receiver only goes away when the watch is replaced or the
+            // process is shutting down
+            let _ = tx.send(res);
+        },
+        notify::Config::default(),
+    )?;
+    for path in paths {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+    Ok((watcher, rx))
+}
+
+/// Keep trying to watch `paths` until it succeeds, a dir may be missing
+/// for a while if it is being replaced
+async fn rewatch(paths: &[PathBuf]) -> (RecommendedWatcher, WatchReceiver) {
+    loop {
+        match start_watch(paths) {
+            Ok(watch) => return watch,
+            Err(e) => {
+                tracing::warn!(
+                    "failed to re-establish cfg watcher, retrying in {REWATCH_DELAY:?}: {e}"
+                );
+                tokio::time::sleep(REWATCH_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Renames, including a temp file being moved over a cfg file, are reported
+/// as `Modify(Name(_))` so they are covered by `Modify`
+fn is_cfg_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+/// Collect every item that arrives within `window` of the first one into a
+/// single batch, so a burst of writes from an editor is only reported once.
+pub fn debounce<S: Stream>(stream: S, window: Duration) -> impl Stream<Item = Vec<S::Item>> {
+    futures::stream::unfold(Box::pin(stream), move |mut stream| async move {
+        let mut batch = vec![stream.next().await?];
+        let deadline = Instant::now() + window;
+        while let Ok(Some(item)) = tokio::time::timeout_at(deadline, stream.next()).await {
+            batch.push(item);
+        }
+        Some((batch, stream))
+    })
+}
+
+/// A single file that changed, as reported to sse clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// The path relative to its cfg dir, when the watcher provided one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Create,
+    Modify,
+    Delete,
+}
+
+impl ChangeKind {
+    /// The name of the sse event sent for this kind of change
+    pub fn event_name(self) -> &'static str {
+        match self {
+            Self::Create => "created",
+            Self::Modify => "modified",
+            Self::Delete => "deleted",
+        }
+    }
+
+    /// The kind of change for the `index`th path of an event, the source of
+    /// a rename no longer exists so it is reported as a delete
+    fn from_event_kind(kind: &EventKind, index: usize) -> Self {
+        match kind {
+            EventKind::Create(_) => Self::Create,
+            EventKind::Remove(_) => Self::Delete,
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Self::Delete,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if index == 0 => Self::Delete,
+            _ => Self::Modify,
+        }
+    }
+}
+
+/// The distinct changes in a debounced batch of events, in the order
+/// they first appeared. Repeats of the same kind of change to the same
+/// path are coalesced into one.
+pub fn changes(cfg_paths: &[PathBuf], batch: &[notify::Event]) -> Vec<Change> {
+    let mut ret: Vec<Change> = Vec::new();
+    for event in batch {
+        let paths = event.paths.iter().map(|path| {
+            let relative = cfg_paths
+                .iter()
+                .find_map(|cfg_path| path.strip_prefix(cfg_path).ok())
+                .filter(|p| !p.as_os_str().is_empty());
+            relative
+                .or_else(|| path.file_name().map(Path::new))
+                .map(|p| p.display().to_string())
+        });
+        let paths: Vec<Option<String>> = if event.paths.is_empty() {
+            vec![None]
+        } else {
+            paths.collect()
+        };
+        for (index, changed) in paths.into_iter().enumerate() {
+            let kind = ChangeKind::from_event_kind(&event.kind, index);
+            let change = Change { changed, kind };
+            if !ret.contains(&change) {
+                ret.push(change);
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn watch_reports_a_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [dir.path().to_path_buf()];
+        let mut stream = std::pin::pin!(watch(&paths).unwrap());
+        std::fs::write(dir.path().join("new.toml"), "name = \"new\"").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(is_cfg_change(&event.kind), "{event:?}");
+        assert!(
+            event.paths.iter().any(|p| p.ends_with("new.toml")),
+            "{event:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn debounce_batches_items_within_the_window() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for (delay, item) in [(0, 1), (100, 2), (100, 3), (1000, 4)] {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                tx.send(item).unwrap();
+            }
+        });
+        let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
+        let batches: Vec<Vec<i32>> = debounce(stream, Duration::from_millis(250)).collect().await;
+        assert_eq!(batches, [vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn removed_and_renamed_away_files_are_deletes() {
+        let cfg = PathBuf::from("/cfg");
+        let batch = [
+            notify::Event::new(EventKind::Remove(notify::event::RemoveKind::File))
+                .add_path(cfg.join("gone.toml")),
+            notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(cfg.join("old.toml"))
+                .add_path(cfg.join("new.toml")),
+        ];
+        assert_eq!(
+            changes(&[cfg], &batch),
+            [
+                Change {
+                    changed: Some("gone.toml".to_string()),
+                    kind: ChangeKind::Delete,
+                },
+                Change {
+                    changed: Some("old.toml".to_string()),
+                    kind: ChangeKind::Delete,
+                },
+                Change {
+                    changed: Some("new.toml".to_string()),
+                    kind: ChangeKind::Modify,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn each_kind_of_change_has_its_own_event() {
+        let cfg = PathBuf::from("/cfg");
+        let batch = [
+            notify::Event::new(EventKind::Create(notify::event::CreateKind::File))
+                .add_path(cfg.join("a.toml")),
+            notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(cfg.join("a.toml")),
+            notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(cfg.join("a.toml")),
+            notify::Event::new(EventKind::Remove(notify::event::RemoveKind::File))
+                .add_path(cfg.join("b.toml")),
+        ];
+        let names: Vec<&str> = changes(&[cfg], &batch)
+            .into_iter()
+            .map(|change| change.kind.event_name())
+            .collect();
+        assert_eq!(names, ["created", "modified", "deleted"]);
+    }
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> S::Item {
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn watcher_errors_re_establish_the_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let broken = RecommendedWatcher::new(|_| {}, notify::Config::default()).unwrap();
+        let mut stream = std::pin::pin!(events(vec![dir.path().to_path_buf()], broken, rx));
+        tx.send(Ok(notify::Event::new(EventKind::Access(
+            notify::event::AccessKind::Any,
+        ))))
+        .unwrap();
+        tx.send(Err(notify::Error::generic("injected"))).unwrap();
+        // anything missed while the watch was down is picked up by a full reload
+        assert_eq!(next(&mut stream).await.kind, EventKind::Any);
+        std::fs::write(dir.path().join("after.toml"), "name = \"after\"").unwrap();
+        let event = next(&mut stream).await;
+        assert!(
+            event.paths.iter().any(|p| p.ends_with("after.toml")),
+            "{event:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn renaming_over_a_cfg_file_is_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [dir.path().to_path_buf()];
+        std::fs::write(dir.path().join("foo.tmp"), "name = \"foo\"").unwrap();
+        let mut stream =
+            std::pin::pin!(debounce(watch(&paths).unwrap(), Duration::from_millis(100)));
+        std::fs::rename(dir.path().join("foo.tmp"), dir.path().join("foo.toml")).unwrap();
+        let batch = next(&mut stream).await;
+        let changes = changes(&paths, &batch);
+        assert!(
+            changes
+                .iter()
+                .any(|c| c.changed.as_deref() == Some("foo.toml") && c.kind != ChangeKind::Delete),
+            "{changes:?}"
+        );
+    }
+}